@@ -11,3 +11,22 @@ pub fn available<T: AsRef<str> + std::fmt::Debug>(domain: T) -> anyhow::Result<b
 
     Ok(false)
 }
+
+/// Check whether given domain name is resolvable within the timeout
+/// 
+/// Domains which couldn't be resolved in time are considered unavailable
+pub fn available_timeout(domain: impl AsRef<str>, timeout: std::time::Duration) -> anyhow::Result<bool> {
+    let domain = domain.as_ref().to_string();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    // Lookup can't be interrupted so the thread is left running after the timeout
+    std::thread::spawn(move || {
+        let _ = sender.send(available(domain));
+    });
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(_) => Ok(false)
+    }
+}
@@ -0,0 +1,21 @@
+/// Replace scheme and host of the url with the base url, keeping its path and query
+///
+/// ```
+/// use anime_game_core::endpoints::rebase_url;
+///
+/// assert_eq!(rebase_url("https://example.com/path/file.zip", "http://mirror.local/"), "http://mirror.local/path/file.zip");
+/// assert_eq!(rebase_url("https://example.com", "http://mirror.local"), "http://mirror.local");
+/// ```
+pub fn rebase_url(url: impl AsRef<str>, base: impl AsRef<str>) -> String {
+    let url = url.as_ref();
+    let base = base.as_ref().trim_end_matches('/');
+
+    let host_start = url.find("://")
+        .map(|pos| pos + 3)
+        .unwrap_or(0);
+
+    match url[host_start..].find('/') {
+        Some(pos) => format!("{base}{}", &url[host_start + pos..]),
+        None => base.to_string()
+    }
+}
@@ -1,24 +1,81 @@
 use std::process::Command;
-use std::io::{Error, ErrorKind};
 use std::os::unix::prelude::PermissionsExt;
 use std::path::PathBuf;
+use std::sync::RwLock;
 
-/// Try to apply hdiff patch
-#[tracing::instrument(level = "debug")]
-pub fn patch<T: Into<PathBuf> + std::fmt::Debug>(file: T, patch: T, output: T) -> std::io::Result<()> {
-    tracing::debug!("Applying hdiff patch");
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+lazy_static::lazy_static! {
+    /// Path to the hpatchz binary specified by the `set_binary_path` function
+    static ref BINARY_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Failed to apply hdiff patch
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("Failed to apply hdiff patch for {file:?} (exit code {exit_code:?}): {stderr}")]
+pub struct HdiffPatchError {
+    /// Path to the patched file
+    pub file: PathBuf,
+
+    /// Exit code of the patcher process
+    ///
+    /// `None` if the process couldn't be started or was killed by a signal
+    pub exit_code: Option<i32>,
+
+    /// Captured stderr of the patcher process, or the error message if it couldn't be started
+    pub stderr: String
+}
+
+/// Use specified hpatchz binary instead of the bundled one
+pub fn set_binary_path(path: impl Into<PathBuf>) {
+    *BINARY_PATH.write().unwrap() = Some(path.into());
+}
+
+/// Get path to the hpatchz binary
+///
+/// Bundled binary is extracted to the temp folder if other one wasn't specified
+fn binary() -> std::io::Result<PathBuf> {
+    if let Some(path) = BINARY_PATH.read().unwrap().as_ref() {
+        return Ok(path.to_owned());
+    }
 
     let hpatchz = super::STORAGE.map("hpatchz")?;
 
     // Allow to execute this binary
     std::fs::set_permissions(&hpatchz, std::fs::Permissions::from_mode(0o777))?;
 
-    let output = Command::new(hpatchz)
+    Ok(PathBuf::from(hpatchz))
+}
+
+/// Check if hpatchz binary exists and can be executed
+pub fn is_available() -> bool {
+    match binary() {
+        Ok(hpatchz) => Command::new(hpatchz).output().is_ok(),
+        Err(_) => false
+    }
+}
+
+/// Try to apply hdiff patch
+#[tracing::instrument(level = "debug")]
+pub fn patch<T: Into<PathBuf> + std::fmt::Debug>(file: T, patch: T, output: T) -> Result<(), HdiffPatchError> {
+    tracing::debug!("Applying hdiff patch");
+
+    let file = file.into();
+
+    let not_started = |err: std::io::Error| HdiffPatchError {
+        file: file.clone(),
+        exit_code: None,
+        stderr: err.to_string()
+    };
+
+    let output = Command::new(binary().map_err(not_started)?)
         .arg("-f")
-        .arg(file.into().as_os_str())
+        .arg(file.as_os_str())
         .arg(patch.into().as_os_str())
         .arg(output.into().as_os_str())
-        .output()?;
+        .output()
+        .map_err(not_started)?;
 
     if String::from_utf8_lossy(output.stdout.as_slice()).contains("patch ok!") {
         Ok(())
@@ -29,6 +86,10 @@ pub fn patch<T: Into<PathBuf> + std::fmt::Debug>(file: T, patch: T, output: T) -
 
         tracing::error!("Failed to apply hdiff patch: {err}");
 
-        Err(Error::new(ErrorKind::Other, format!("Failed to apply hdiff patch: {err}")))
+        Err(HdiffPatchError {
+            file,
+            exit_code: output.status.code(),
+            stderr: err.to_string()
+        })
     }
 }
@@ -1,21 +1,89 @@
 pub mod schema;
 
+use std::sync::RwLock;
+use std::time::Duration;
+
+use cached::Cached;
+
 use crate::genshin::consts::GameEdition;
 
+lazy_static::lazy_static! {
+    static ref API_BASE_URL: RwLock<Option<String>> = RwLock::new(None);
+    static ref PACKAGE_BASE_URL: RwLock<Option<String>> = RwLock::new(None);
+}
+
+/// Use specified host instead of the official API one, e.g. `https://api.mirror.local`
+///
+/// Path and query of the `GameEdition::api_uri` are kept. `None` restores the official host
+pub fn set_api_base_url(url: Option<String>) {
+    *API_BASE_URL.write().unwrap() = url;
+}
+
+/// Use specified host instead of the official one in the packages urls returned by the API
+///
+/// Paths of the packages urls are kept. `None` restores the official host
+pub fn set_package_base_url(url: Option<String>) {
+    *PACKAGE_BASE_URL.write().unwrap() = url;
+}
+
+#[inline]
+/// Get host specified by the `set_api_base_url` function
+pub fn api_base_url() -> Option<String> {
+    API_BASE_URL.read().unwrap().clone()
+}
+
+#[inline]
+/// Get host specified by the `set_package_base_url` function
+pub fn package_base_url() -> Option<String> {
+    PACKAGE_BASE_URL.read().unwrap().clone()
+}
+
+/// Keep fetched API responses for the given time
+///
+/// Responses are cached until the `clear_cache` call by default
+pub fn set_cache_ttl(ttl: Duration) {
+    REQUEST.lock().unwrap().cache_set_lifespan(ttl.as_secs());
+}
+
+/// Remove cached API responses, so they're fetched again by the next `request` calls
+pub fn clear_cache() {
+    REQUEST.lock().unwrap().cache_clear();
+}
+
+#[inline]
+/// Fetch API response ignoring the cached one
+///
+/// Fetched response is cached for the next `request` calls
+pub fn refresh(game_edition: GameEdition) -> anyhow::Result<schema::GamePackage> {
+    request_prime_cache(game_edition)
+}
+
 #[cached::proc_macro::cached(
-    key = "GameEdition",
-    convert = r#"{ game_edition }"#,
+    ty = "cached::TimedCache<(GameEdition, Option<String>, Option<String>), schema::GamePackage>",
+    create = "{ cached::TimedCache::with_lifespan(u64::MAX) }",
+    key = "(GameEdition, Option<String>, Option<String>)",
+    convert = r#"{ (game_edition, api_base_url(), package_base_url()) }"#,
     result
 )]
 #[tracing::instrument(level = "trace")]
 pub fn request(game_edition: GameEdition) -> anyhow::Result<schema::GamePackage> {
     tracing::trace!("Fetching API for {:?}", game_edition);
 
-    let schema: schema::Response = minreq::get(game_edition.api_uri())
+    let uri = match api_base_url() {
+        Some(base) => crate::endpoints::rebase_url(game_edition.api_uri(), base),
+        None => game_edition.api_uri().to_string()
+    };
+
+    let schema: schema::Response = minreq::get(uri)
         .with_timeout(*crate::REQUESTS_TIMEOUT)
         .send()?.json()?;
 
-    schema.data.game_packages.into_iter()
+    let package = schema.data.game_packages.into_iter()
         .find(|game| game.game.biz.starts_with("hk4e_"))
-        .ok_or_else(|| anyhow::anyhow!("Failed to find the game in the API"))
+        .ok_or_else(|| anyhow::anyhow!("Failed to find the game in the API"))?;
+
+    Ok(match package_base_url() {
+        Some(base) => package.with_package_base_url(&base),
+        None => package
+    })
 }
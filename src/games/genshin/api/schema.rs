@@ -19,6 +19,33 @@ pub struct GamePackage {
     pub pre_download: Option<GamePredownloadInfo>
 }
 
+impl GamePackage {
+    /// Replace host of all the packages urls with the base url
+    pub fn with_package_base_url(mut self, base: &str) -> Self {
+        let rebase = |url: &mut String| *url = crate::endpoints::rebase_url(&url, base);
+
+        let latest = std::iter::once(&mut self.main.major)
+            .chain(self.pre_download.as_mut().and_then(|info| info.major.as_mut()));
+
+        for info in latest {
+            info.game_pkgs.iter_mut().for_each(|segment| rebase(&mut segment.url));
+            info.audio_pkgs.iter_mut().for_each(|package| rebase(&mut package.url));
+
+            rebase(&mut info.res_list_url);
+        }
+
+        let patches = self.main.patches.iter_mut()
+            .chain(self.pre_download.iter_mut().flat_map(|info| info.patches.iter_mut()));
+
+        for patch in patches {
+            patch.game_pkgs.iter_mut().for_each(|segment| rebase(&mut segment.url));
+            patch.audio_pkgs.iter_mut().for_each(|package| rebase(&mut package.url));
+        }
+
+        self
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct GameId {
     pub id: String,
@@ -2,13 +2,19 @@ use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+use serde::{Serialize, Deserialize};
+
 use crate::version::Version;
 use crate::traits::prelude::*;
 
 use super::api;
+use super::api::schema::{GamePackage, GameLatestInfo};
 use super::consts::*;
 use super::version_diff::*;
 
+#[cfg(feature = "install")]
+use crate::installer::{options::InstallOptions, report::InstallReport};
+
 use super::voice_data::locale::VoiceLocale;
 use super::voice_data::package::VoicePackage;
 
@@ -57,8 +63,9 @@ impl GameExt for Game {
         }
 
         let stored_version = std::fs::read(self.path.join(".version"))
-            .map(|version| Version::new(version[0], version[1], version[2]))
-            .ok();
+            .ok()
+            .filter(|version| version.len() >= 3)
+            .map(|version| Version::new(version[0], version[1], version[2]));
 
         let file = File::open(self.path.join(self.edition.data_folder()).join("globalgamemanagers"))?;
 
@@ -127,7 +134,39 @@ impl GameExt for Game {
     }
 }
 
+/// Game version known by the API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub version: Version,
+
+    /// Version is not released yet and can only be pre-downloaded
+    pub predownload: bool,
+
+    /// Urls of the full game archive segments
+    ///
+    /// Empty for older versions which can only be updated from
+    pub segments_uris: Vec<String>,
+
+    /// Total size of the full game archive segments
+    pub downloaded_size: u64
+}
+
 impl Game {
+    /// Get version of the game installed into the `path`
+    ///
+    /// Version is parsed from the `<data folder>/globalgamemanagers` file and the `.version`
+    /// file stored by the installation methods, preferring the greater one. All the game
+    /// editions are checked, so the data folder's name doesn't need to be known
+    ///
+    /// Return `None` if the game is not installed or its version can't be found
+    pub fn get_installed_version(path: impl AsRef<Path>) -> Option<Version> {
+        let path = path.as_ref();
+
+        GameEdition::list().iter()
+            .filter(|edition| path.join(edition.data_folder()).exists())
+            .find_map(|edition| Self::new(path, *edition).get_version().ok())
+    }
+
     /// Get list of installed voice packages
     pub fn get_voice_packages(&self) -> anyhow::Result<Vec<VoicePackage>> {
         let content = std::fs::read_dir(get_voice_packages_path(&self.path, self.edition))?;
@@ -145,6 +184,35 @@ impl Game {
         Ok(packages)
     }
 
+    /// Get base url of the latest game files
+    ///
+    /// Files listed in the `pkg_version` manifest can be downloaded
+    /// from this url joined with their relative paths
+    pub fn get_files_base_url(&self) -> anyhow::Result<String> {
+        Ok(api::request(self.edition)?.main.major.res_list_url)
+    }
+
+    /// Get download url of the latest version of the game file
+    ///
+    /// `relative_path` must be relative to the game's folder, so
+    /// if your file is e.g. `/path/to/[AnimeGame]/[AnimeGame_Data]/level0`,
+    /// then `relative_path` must be `[AnimeGame_Data]/level0`
+    pub fn get_file_url(&self, relative_path: impl AsRef<Path>) -> anyhow::Result<String> {
+        let base_url = self.get_files_base_url()?;
+        let relative_path = relative_path.as_ref().to_string_lossy().replace('\\', "/");
+
+        Ok(format!("{}/{}", base_url.trim_end_matches('/'), relative_path.trim_start_matches('/')))
+    }
+
+    #[inline]
+    /// Get all the game versions known by the API sorted from the newest to the oldest
+    ///
+    /// Besides latest and pre-downloadable versions, the API lists the ones
+    /// which can be updated to the latest version. They can't be fully downloaded
+    pub fn list_versions(edition: GameEdition) -> anyhow::Result<Vec<VersionInfo>> {
+        Ok(package_versions(&api::request(edition)?))
+    }
+
     #[tracing::instrument(level = "debug", ret)]
     pub fn try_get_diff(&self) -> anyhow::Result<VersionDiff> {
         tracing::debug!("Trying to find version diff for the game");
@@ -169,9 +237,15 @@ impl Game {
 
                             edition: self.edition,
 
+                            kind: DiffKind::Game,
+
                             downloaded_size,
                             unpacked_size,
 
+                            segments_checksums: Some(response.main.major.game_pkgs.iter()
+                                .map(|segment| segment.md5.clone())
+                                .collect()),
+
                             segments_uris: response.main.major.game_pkgs.into_iter()
                                 .map(|segment| segment.url)
                                 .collect(),
@@ -211,7 +285,9 @@ impl Game {
                                     latest: Version::from_str(predownload_major.version).unwrap(),
 
                                     uri: diff.game_pkgs[0].url.clone(), // TODO: can be a hard issue in future
+                                    checksum: Some(diff.game_pkgs[0].md5.clone()),
                                     edition: self.edition,
+                                    kind: DiffKind::Game,
 
                                     downloaded_size,
                                     unpacked_size,
@@ -227,7 +303,8 @@ impl Game {
 
                 Ok(VersionDiff::Latest {
                     version: current,
-                    edition: self.edition
+                    edition: self.edition,
+                    kind: DiffKind::Game
                 })
             }
 
@@ -249,7 +326,9 @@ impl Game {
                             latest: Version::from_str(response.main.major.version).unwrap(),
 
                             uri: diff.game_pkgs[0].url.clone(), // TODO: can be a hard issue in future
+                            checksum: Some(diff.game_pkgs[0].md5.clone()),
                             edition: self.edition,
+                            kind: DiffKind::Game,
 
                             downloaded_size,
                             unpacked_size,
@@ -264,7 +343,8 @@ impl Game {
                 Ok(VersionDiff::Outdated {
                     current,
                     latest: Version::from_str(response.main.major.version).unwrap(),
-                    edition: self.edition
+                    edition: self.edition,
+                    kind: DiffKind::Game
                 })
             }
         }
@@ -285,9 +365,15 @@ impl Game {
 
                 edition: self.edition,
 
+                kind: DiffKind::Game,
+
                 downloaded_size,
                 unpacked_size,
 
+                segments_checksums: Some(response.main.major.game_pkgs.iter()
+                    .map(|segment| segment.md5.clone())
+                    .collect()),
+
                 segments_uris: response.main.major.game_pkgs.into_iter()
                     .map(|segment| segment.url)
                     .collect(),
@@ -298,4 +384,169 @@ impl Game {
             })
         }
     }
+
+    #[cfg(feature = "install")]
+    /// Install or update the game to the `target` version and repair its files
+    ///
+    /// The difference is installed if the game is not installed or outdated, then the game
+    /// files are verified using the latest `pkg_version` manifest and corrupted ones are
+    /// re-downloaded. Safe to call repeatedly: nothing is changed if all the files match.
+    /// Voice packages are not updated
+    ///
+    /// Fail if `target` is not the latest version of the game
+    pub fn ensure_installed(&self, target: Version, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> anyhow::Result<InstallReport> {
+        let diff = self.try_get_diff()?;
+
+        let mut report = match &diff {
+            // Already installed or only pre-downloading is available
+            VersionDiff::Latest { version, .. } |
+            VersionDiff::Predownload { current: version, .. } if *version == target => InstallReport::default(),
+
+            VersionDiff::Diff { latest, .. } |
+            VersionDiff::NotInstalled { latest, .. } if *latest == target => diff.install_to_by(&self.path, options, updater.clone())?,
+
+            VersionDiff::Outdated { current, .. } => anyhow::bail!("Game version {current} is too outdated and can't be updated"),
+
+            _ => anyhow::bail!("Game version {target} is not available")
+        };
+
+        tracing::debug!("Repairing game files");
+
+        (updater)(DiffUpdate::RepairingStarted);
+
+        let files = super::repairer::try_get_integrity_files(self.edition, None)?;
+
+        let progress = updater.clone();
+
+        report.repaired_files = crate::repairer::repair_files(&self.path, &files, move |current, total| {
+            (progress)(DiffUpdate::RepairingProgress(current, total));
+        })?;
+
+        (updater)(DiffUpdate::RepairingFinished);
+
+        Ok(report)
+    }
+}
+
+/// List versions of the game package sorted from the newest to the oldest
+fn package_versions(package: &GamePackage) -> Vec<VersionInfo> {
+    fn full_version(info: &GameLatestInfo, predownload: bool) -> Option<VersionInfo> {
+        Some(VersionInfo {
+            version: Version::from_str(&info.version)?,
+            predownload,
+            segments_uris: info.game_pkgs.iter()
+                .map(|segment| segment.url.clone())
+                .collect(),
+            downloaded_size: info.game_pkgs.iter()
+                .flat_map(|segment| segment.size.parse::<u64>())
+                .sum()
+        })
+    }
+
+    let mut versions = package.pre_download.as_ref()
+        .and_then(|predownload| predownload.major.as_ref())
+        .and_then(|major| full_version(major, true))
+        .into_iter()
+        .chain(full_version(&package.main.major, false))
+        .collect::<Vec<_>>();
+
+    for patch in &package.main.patches {
+        let Some(version) = Version::from_str(&patch.version) else {
+            tracing::warn!("Failed to parse game version: {}", patch.version);
+
+            continue;
+        };
+
+        if !versions.iter().any(|info| info.version == version) {
+            versions.push(VersionInfo {
+                version,
+                predownload: false,
+                segments_uris: Vec::new(),
+                downloaded_size: 0
+            });
+        }
+    }
+
+    versions.sort_by_key(|info| std::cmp::Reverse(info.version));
+
+    versions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_versions() {
+        let release = |version: &str, segments: u64, patches: &[&str]| serde_json::json!({
+            "major": {
+                "version": version,
+                "game_pkgs": (0..segments).map(|i| serde_json::json!({
+                    "url": format!("https://example.com/{version}.zip.00{i}"),
+                    "md5": "",
+                    "size": "10",
+                    "decompressed_size": "30"
+                })).collect::<Vec<_>>(),
+                "audio_pkgs": [],
+                "res_list_url": ""
+            },
+            "patches": patches.iter().map(|version| serde_json::json!({
+                "version": version,
+                "game_pkgs": [],
+                "audio_pkgs": []
+            })).collect::<Vec<_>>()
+        });
+
+        let package: GamePackage = serde_json::from_value(serde_json::json!({
+            "game": {
+                "id": "",
+                "biz": ""
+            },
+            "main": release("1.2.0", 2, &["1.0.0", "1.1.0"]),
+            "pre_download": release("1.3.0", 1, &["1.2.0"])
+        })).unwrap();
+
+        let versions = package_versions(&package);
+
+        assert_eq!(versions.iter().map(|info| info.version).collect::<Vec<_>>(), [
+            Version::new(1, 3, 0),
+            Version::new(1, 2, 0),
+            Version::new(1, 1, 0),
+            Version::new(1, 0, 0)
+        ]);
+
+        assert!(versions[0].predownload);
+        assert!(!versions[1].predownload);
+
+        assert_eq!(versions[1].segments_uris.len(), 2);
+        assert_eq!(versions[1].downloaded_size, 20);
+
+        assert!(versions[2].segments_uris.is_empty());
+    }
+
+    #[test]
+    fn test_get_installed_version() {
+        let path = std::env::temp_dir().join(format!(".anime-game-core-test-installed-version-{}", std::process::id()));
+        let data_folder = path.join(GameEdition::Global.data_folder());
+
+        assert_eq!(Game::get_installed_version(&path), None);
+
+        let mut globalgamemanagers = vec![1; 4000];
+
+        globalgamemanagers.extend_from_slice(b"\x002.5.0_1234567");
+
+        std::fs::create_dir_all(&data_folder).unwrap();
+        std::fs::write(data_folder.join("globalgamemanagers"), globalgamemanagers).unwrap();
+
+        // Broken version file is ignored
+        std::fs::write(path.join(".version"), [2]).unwrap();
+
+        assert_eq!(Game::get_installed_version(&path), Some(Version::new(2, 5, 0)));
+
+        std::fs::write(path.join(".version"), [2, 5, 1]).unwrap();
+
+        assert_eq!(Game::get_installed_version(&path), Some(Version::new(2, 5, 1)));
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
 }
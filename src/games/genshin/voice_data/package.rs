@@ -373,6 +373,7 @@ impl VoicePackage {
                                     current,
                                     latest: Version::from_str(predownload_major.version).unwrap(),
                                     uri: diff.url,
+                                    checksum: Some(diff.md5),
 
                                     downloaded_size: diff.size.parse::<u64>().unwrap(),
                                     unpacked_size: diff.decompressed_size.parse::<u64>().unwrap(),
@@ -393,7 +394,8 @@ impl VoicePackage {
                                     },
 
                                     temp_folder: None,
-                                    edition: game_edition
+                                    edition: game_edition,
+                                    kind: DiffKind::VoicePackage(self.locale())
                                 })
                             }
                         }
@@ -402,7 +404,8 @@ impl VoicePackage {
 
                 Ok(VersionDiff::Latest {
                     version: current,
-                    edition: game_edition
+                    edition: game_edition,
+                    kind: DiffKind::VoicePackage(self.locale())
                 })
             }
 
@@ -417,6 +420,7 @@ impl VoicePackage {
                             current,
                             latest: Version::from_str(response.main.major.version).unwrap(),
                             uri: diff.url,
+                            checksum: Some(diff.md5),
 
                             downloaded_size: diff.size.parse::<u64>().unwrap(),
                             unpacked_size: diff.decompressed_size.parse::<u64>().unwrap(),
@@ -437,7 +441,8 @@ impl VoicePackage {
                             },
 
                             temp_folder: None,
-                            edition: game_edition
+                            edition: game_edition,
+                            kind: DiffKind::VoicePackage(self.locale())
                         })
                     }
                 }
@@ -445,7 +450,8 @@ impl VoicePackage {
                 Ok(VersionDiff::Outdated {
                     current,
                     latest: Version::from_str(response.main.major.version).unwrap(),
-                    edition: game_edition
+                    edition: game_edition,
+                    kind: DiffKind::VoicePackage(self.locale())
                 })
             }
         }
@@ -458,6 +464,7 @@ impl VoicePackage {
             Ok(VersionDiff::NotInstalled {
                 latest: Version::from_str(response.main.major.version).unwrap(),
                 segments_uris: vec![latest.url],
+                segments_checksums: Some(vec![latest.md5]),
 
                 downloaded_size: latest.size.parse::<u64>().unwrap(),
                 unpacked_size: latest.decompressed_size.parse::<u64>().unwrap(),
@@ -478,7 +485,8 @@ impl VoicePackage {
                 },
 
                 temp_folder: None,
-                edition: game_edition
+                edition: game_edition,
+                kind: DiffKind::VoicePackage(self.locale())
             })
         }
     }
@@ -2,9 +2,35 @@ pub mod schema;
 
 mod schema_old;
 
+use std::time::Duration;
+
+use cached::Cached;
+
 use crate::honkai::consts::GameEdition;
 
+/// Keep fetched API responses for the given time
+///
+/// Responses are cached until the `clear_cache` call by default
+pub fn set_cache_ttl(ttl: Duration) {
+    REQUEST.lock().unwrap().cache_set_lifespan(ttl.as_secs());
+}
+
+/// Remove cached API responses, so they're fetched again by the next `request` calls
+pub fn clear_cache() {
+    REQUEST.lock().unwrap().cache_clear();
+}
+
+#[inline]
+/// Fetch API response ignoring the cached one
+///
+/// Fetched response is cached for the next `request` calls
+pub fn refresh(game_edition: GameEdition) -> anyhow::Result<schema::GamePackage> {
+    request_prime_cache(game_edition)
+}
+
 #[cached::proc_macro::cached(
+    ty = "cached::TimedCache<GameEdition, schema::GamePackage>",
+    create = "{ cached::TimedCache::with_lifespan(u64::MAX) }",
     key = "GameEdition",
     convert = r#"{ game_edition }"#,
     result
@@ -54,8 +54,9 @@ impl GameExt for Game {
         }
 
         let stored_version = std::fs::read(self.path.join(".version"))
-            .map(|version| Version::new(version[0], version[1], version[2]))
-            .ok();
+            .ok()
+            .filter(|version| version.len() >= 3)
+            .map(|version| Version::new(version[0], version[1], version[2]));
 
         let file = File::open(self.path.join(self.edition.data_folder()).join("globalgamemanagers"))?;
 
@@ -122,6 +123,41 @@ impl GameExt for Game {
 }
 
 impl Game {
+    /// Get version of the game installed into the `path`
+    ///
+    /// Version is parsed from the `<data folder>/globalgamemanagers` file and the `.version`
+    /// file stored by the installation methods, preferring the greater one. All the game
+    /// editions are checked, so the data folder's name doesn't need to be known
+    ///
+    /// Return `None` if the game is not installed or its version can't be found
+    pub fn get_installed_version(path: impl AsRef<Path>) -> Option<Version> {
+        let path = path.as_ref();
+
+        GameEdition::list().iter()
+            .filter(|edition| path.join(edition.data_folder()).exists())
+            .find_map(|edition| Self::new(path, *edition).get_version().ok())
+    }
+
+    /// Get base url of the latest game files
+    ///
+    /// Files listed in the `pkg_version` manifest can be downloaded
+    /// from this url joined with their relative paths
+    pub fn get_files_base_url(&self) -> anyhow::Result<String> {
+        Ok(api::request(self.edition)?.main.major.res_list_url)
+    }
+
+    /// Get download url of the latest version of the game file
+    ///
+    /// `relative_path` must be relative to the game's folder, so
+    /// if your file is e.g. `/path/to/[AnimeGame]/[AnimeGame_Data]/level0`,
+    /// then `relative_path` must be `[AnimeGame_Data]/level0`
+    pub fn get_file_url(&self, relative_path: impl AsRef<Path>) -> anyhow::Result<String> {
+        let base_url = self.get_files_base_url()?;
+        let relative_path = relative_path.as_ref().to_string_lossy().replace('\\', "/");
+
+        Ok(format!("{}/{}", base_url.trim_end_matches('/'), relative_path.trim_start_matches('/')))
+    }
+
     #[tracing::instrument(level = "debug", ret)]
     pub fn try_get_diff(&self) -> anyhow::Result<VersionDiff> {
         tracing::debug!("Trying to find version diff for the game");
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use super::consts::GameEdition;
 
 /// Check whether telemetry servers disabled
@@ -26,3 +28,28 @@ pub fn is_disabled(game_edition: GameEdition) -> anyhow::Result<Option<String>>
 
     Ok(None)
 }
+
+/// Get all the telemetry servers which are not disabled
+/// 
+/// Servers which couldn't be resolved within the timeout are considered disabled
+/// 
+/// ```no_run
+/// use std::time::Duration;
+/// 
+/// use anime_game_core::honkai::telemetry;
+/// use anime_game_core::honkai::consts::GameEdition;
+/// 
+/// for server in telemetry::enabled_servers(GameEdition::Global, Duration::from_secs(5)) {
+///     println!("Telemetry server is not disabled: {server}");
+/// }
+/// ```
+#[tracing::instrument(level = "debug")]
+pub fn enabled_servers(game_edition: GameEdition, timeout: Duration) -> Vec<String> {
+    tracing::debug!("Checking telemetry servers status");
+
+    game_edition.telemetry_servers().iter()
+        .filter(|server| crate::check_domain::available_timeout(server, timeout).unwrap_or(false))
+        .inspect(|server| tracing::warn!("Server is not disabled: {server}"))
+        .map(|server| server.to_string())
+        .collect()
+}
@@ -89,6 +89,36 @@ pub enum VersionDiff {
 }
 
 impl VersionDiff {
+    /// Get url of the archive this difference is downloaded from
+    ///
+    /// ```
+    /// use anime_game_core::prelude::*;
+    /// use anime_game_core::honkai::version_diff::VersionDiff;
+    ///
+    /// let diff = VersionDiff::NotInstalled {
+    ///     latest: Version::new(1, 0, 0),
+    ///     url: String::from("https://example.com/game.7z"),
+    ///     downloaded_size: 0,
+    ///     unpacked_size: 0,
+    ///     installation_path: None,
+    ///     version_file_path: None,
+    ///     temp_folder: None
+    /// };
+    ///
+    /// assert_eq!(diff.download_url(), Some("https://example.com/game.7z"));
+    /// assert_eq!(VersionDiff::Latest(Version::new(1, 0, 0)).download_url(), None);
+    /// ```
+    pub fn download_url(&self) -> Option<&str> {
+        match self {
+            // Can't be installed
+            Self::Latest(_) => None,
+
+            // Can be installed
+            Self::Diff { url, .. } |
+            Self::NotInstalled { url, .. } => Some(url)
+        }
+    }
+
     /// Get `.version` file path
     pub fn version_file_path(&self) -> Option<PathBuf> {
         match self {
@@ -202,15 +232,9 @@ impl VersionDiffExt for VersionDiff {
         }
     }
 
+    #[inline]
     fn downloading_uri(&self) -> Option<String> {
-        match self {
-            // Can't be installed
-            Self::Latest(_) => None,
-
-            // Can be installed
-            Self::Diff { url, .. } |
-            Self::NotInstalled { url, .. } => Some(url.to_owned())
-        }
+        self.download_url().map(String::from)
     }
 
     fn download_as(&mut self, path: impl AsRef<Path>, progress: impl Fn(u64, u64) + Send + 'static) -> Result<(), Self::Error> {
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use super::consts::TELEMETRY_SERVERS;
 
 /// Check whether telemetry servers disabled
@@ -25,3 +27,27 @@ pub fn is_disabled() -> anyhow::Result<Option<String>> {
 
     Ok(None)
 }
+
+/// Get all the telemetry servers which are not disabled
+/// 
+/// Servers which couldn't be resolved within the timeout are considered disabled
+/// 
+/// ```no_run
+/// use std::time::Duration;
+/// 
+/// use anime_game_core::pgr::telemetry;
+/// 
+/// for server in telemetry::enabled_servers(Duration::from_secs(5)) {
+///     println!("Telemetry server is not disabled: {server}");
+/// }
+/// ```
+#[tracing::instrument(level = "debug")]
+pub fn enabled_servers(timeout: Duration) -> Vec<String> {
+    tracing::debug!("Checking telemetry servers status");
+
+    TELEMETRY_SERVERS.iter()
+        .filter(|server| crate::check_domain::available_timeout(server, timeout).unwrap_or(false))
+        .inspect(|server| tracing::warn!("Server is not disabled: {server}"))
+        .map(|server| server.to_string())
+        .collect()
+}
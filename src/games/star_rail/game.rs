@@ -2,13 +2,19 @@ use std::fs::File;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+use serde::{Serialize, Deserialize};
+
 use crate::version::Version;
 use crate::traits::game::GameExt;
 
 use super::api;
+use super::api::schema::{GamePackage, GameLatestInfo};
 use super::consts::*;
 use super::version_diff::*;
 
+#[cfg(feature = "install")]
+use crate::installer::{options::InstallOptions, report::InstallReport};
+
 use super::voice_data::locale::VoiceLocale;
 use super::voice_data::package::VoicePackage;
 
@@ -57,8 +63,9 @@ impl GameExt for Game {
         }
 
         let stored_version = std::fs::read(self.path.join(".version"))
-            .map(|version| Version::new(version[0], version[1], version[2]))
-            .ok();
+            .ok()
+            .filter(|version| version.len() >= 3)
+            .map(|version| Version::new(version[0], version[1], version[2]));
 
         let file = File::open(self.path.join(self.edition.data_folder()).join("data.unity3d"))?;
 
@@ -128,7 +135,39 @@ impl GameExt for Game {
     }
 }
 
+/// Game version known by the API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub version: Version,
+
+    /// Version is not released yet and can only be pre-downloaded
+    pub predownload: bool,
+
+    /// Urls of the full game archive segments
+    ///
+    /// Empty for older versions which can only be updated from
+    pub segments_uris: Vec<String>,
+
+    /// Total size of the full game archive segments
+    pub downloaded_size: u64
+}
+
 impl Game {
+    /// Get version of the game installed into the `path`
+    ///
+    /// Version is parsed from the `<data folder>/globalgamemanagers` file and the `.version`
+    /// file stored by the installation methods, preferring the greater one. All the game
+    /// editions are checked, so the data folder's name doesn't need to be known
+    ///
+    /// Return `None` if the game is not installed or its version can't be found
+    pub fn get_installed_version(path: impl AsRef<Path>) -> Option<Version> {
+        let path = path.as_ref();
+
+        GameEdition::list().iter()
+            .filter(|edition| path.join(edition.data_folder()).exists())
+            .find_map(|edition| Self::new(path, *edition).get_version().ok())
+    }
+
     /// Get list of installed voice packages
     pub fn get_voice_packages(&self) -> anyhow::Result<Vec<VoicePackage>> {
         let content = std::fs::read_dir(get_voice_packages_path(&self.path, self.edition))?;
@@ -146,6 +185,35 @@ impl Game {
         Ok(packages)
     }
 
+    /// Get base url of the latest game files
+    ///
+    /// Files listed in the `pkg_version` manifest can be downloaded
+    /// from this url joined with their relative paths
+    pub fn get_files_base_url(&self) -> anyhow::Result<String> {
+        Ok(api::request(self.edition)?.main.major.res_list_url)
+    }
+
+    /// Get download url of the latest version of the game file
+    ///
+    /// `relative_path` must be relative to the game's folder, so
+    /// if your file is e.g. `/path/to/[AnimeGame]/[AnimeGame_Data]/level0`,
+    /// then `relative_path` must be `[AnimeGame_Data]/level0`
+    pub fn get_file_url(&self, relative_path: impl AsRef<Path>) -> anyhow::Result<String> {
+        let base_url = self.get_files_base_url()?;
+        let relative_path = relative_path.as_ref().to_string_lossy().replace('\\', "/");
+
+        Ok(format!("{}/{}", base_url.trim_end_matches('/'), relative_path.trim_start_matches('/')))
+    }
+
+    #[inline]
+    /// Get all the game versions known by the API sorted from the newest to the oldest
+    ///
+    /// Besides latest and pre-downloadable versions, the API lists the ones
+    /// which can be updated to the latest version. They can't be fully downloaded
+    pub fn list_versions(edition: GameEdition) -> anyhow::Result<Vec<VersionInfo>> {
+        Ok(package_versions(&api::request(edition)?))
+    }
+
     #[tracing::instrument(level = "debug", ret)]
     pub fn try_get_diff(&self) -> anyhow::Result<VersionDiff> {
         tracing::debug!("Trying to find version diff for the game");
@@ -168,10 +236,15 @@ impl Game {
                         return Ok(VersionDiff::NotInstalled {
                             latest: Version::from_str(&response.main.major.version).unwrap(),
                             edition: self.edition,
+                            kind: DiffKind::Game,
 
                             downloaded_size,
                             unpacked_size,
 
+                            segments_checksums: Some(response.main.major.game_pkgs.iter()
+                                .map(|segment| segment.md5.clone())
+                                .collect()),
+
                             segments_uris: response.main.major.game_pkgs.into_iter()
                                 .map(|segment| segment.url)
                                 .collect(),
@@ -211,7 +284,9 @@ impl Game {
                                     latest: Version::from_str(predownload_major.version).unwrap(),
 
                                     uri: diff.game_pkgs[0].url.clone(), // TODO: can be a hard issue in future
+                                    checksum: Some(diff.game_pkgs[0].md5.clone()),
                                     edition: self.edition,
+                                    kind: DiffKind::Game,
 
                                     downloaded_size,
                                     unpacked_size,
@@ -227,7 +302,8 @@ impl Game {
 
                 Ok(VersionDiff::Latest {
                     version: current,
-                    edition: self.edition
+                    edition: self.edition,
+                    kind: DiffKind::Game
                 })
             }
 
@@ -249,7 +325,9 @@ impl Game {
                             latest: Version::from_str(response.main.major.version).unwrap(),
 
                             uri: diff.game_pkgs[0].url.clone(), // TODO: can be a hard issue in future
+                            checksum: Some(diff.game_pkgs[0].md5.clone()),
                             edition: self.edition,
+                            kind: DiffKind::Game,
 
                             downloaded_size,
                             unpacked_size,
@@ -264,7 +342,8 @@ impl Game {
                 Ok(VersionDiff::Outdated {
                     current,
                     latest: Version::from_str(response.main.major.version).unwrap(),
-                    edition: self.edition
+                    edition: self.edition,
+                    kind: DiffKind::Game
                 })
             }
         }
@@ -283,10 +362,15 @@ impl Game {
             Ok(VersionDiff::NotInstalled {
                 latest: Version::from_str(&response.main.major.version).unwrap(),
                 edition: self.edition,
+                kind: DiffKind::Game,
 
                 downloaded_size,
                 unpacked_size,
 
+                segments_checksums: Some(response.main.major.game_pkgs.iter()
+                    .map(|segment| segment.md5.clone())
+                    .collect()),
+
                 segments_uris: response.main.major.game_pkgs.into_iter()
                     .map(|segment| segment.url)
                     .collect(),
@@ -297,4 +381,90 @@ impl Game {
             })
         }
     }
+
+    #[cfg(feature = "install")]
+    /// Install or update the game to the `target` version and repair its files
+    ///
+    /// The difference is installed if the game is not installed or outdated, then the game
+    /// files are verified using the latest `pkg_version` manifest and corrupted ones are
+    /// re-downloaded. Safe to call repeatedly: nothing is changed if all the files match.
+    /// Voice packages are not updated
+    ///
+    /// Fail if `target` is not the latest version of the game
+    pub fn ensure_installed(&self, target: Version, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> anyhow::Result<InstallReport> {
+        let diff = self.try_get_diff()?;
+
+        let mut report = match &diff {
+            // Already installed or only pre-downloading is available
+            VersionDiff::Latest { version, .. } |
+            VersionDiff::Predownload { current: version, .. } if *version == target => InstallReport::default(),
+
+            VersionDiff::Diff { latest, .. } |
+            VersionDiff::NotInstalled { latest, .. } if *latest == target => diff.install_to_by(&self.path, options, updater.clone())?,
+
+            VersionDiff::Outdated { current, .. } => anyhow::bail!("Game version {current} is too outdated and can't be updated"),
+
+            _ => anyhow::bail!("Game version {target} is not available")
+        };
+
+        tracing::debug!("Repairing game files");
+
+        (updater)(DiffUpdate::RepairingStarted);
+
+        let files = super::repairer::try_get_integrity_files(self.edition, None)?;
+
+        let progress = updater.clone();
+
+        report.repaired_files = crate::repairer::repair_files(&self.path, &files, move |current, total| {
+            (progress)(DiffUpdate::RepairingProgress(current, total));
+        })?;
+
+        (updater)(DiffUpdate::RepairingFinished);
+
+        Ok(report)
+    }
+}
+
+/// List versions of the game package sorted from the newest to the oldest
+fn package_versions(package: &GamePackage) -> Vec<VersionInfo> {
+    fn full_version(info: &GameLatestInfo, predownload: bool) -> Option<VersionInfo> {
+        Some(VersionInfo {
+            version: Version::from_str(&info.version)?,
+            predownload,
+            segments_uris: info.game_pkgs.iter()
+                .map(|segment| segment.url.clone())
+                .collect(),
+            downloaded_size: info.game_pkgs.iter()
+                .flat_map(|segment| segment.size.parse::<u64>())
+                .sum()
+        })
+    }
+
+    let mut versions = package.pre_download.as_ref()
+        .and_then(|predownload| predownload.major.as_ref())
+        .and_then(|major| full_version(major, true))
+        .into_iter()
+        .chain(full_version(&package.main.major, false))
+        .collect::<Vec<_>>();
+
+    for patch in &package.main.patches {
+        let Some(version) = Version::from_str(&patch.version) else {
+            tracing::warn!("Failed to parse game version: {}", patch.version);
+
+            continue;
+        };
+
+        if !versions.iter().any(|info| info.version == version) {
+            versions.push(VersionInfo {
+                version,
+                predownload: false,
+                segments_uris: Vec::new(),
+                downloaded_size: 0
+            });
+        }
+    }
+
+    versions.sort_by_key(|info| std::cmp::Reverse(info.version));
+
+    versions
 }
@@ -1,83 +1,64 @@
 use std::path::{Path, PathBuf};
 use std::os::unix::prelude::PermissionsExt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::ops::ControlFlow;
 
 use serde::{Serialize, Deserialize};
-use thiserror::Error;
 
 use super::consts::GameEdition;
+use super::api::schema::GamePackage;
+use super::voice_data::locale::VoiceLocale;
 
 use crate::version::Version;
 use crate::traits::version_diff::VersionDiffExt;
 
+#[cfg(feature = "install")]
+pub use crate::installer::diff::{
+    DiffUpdate,
+    DiffDownloadingError,
+    DownloadedArchive,
+    install_many,
+    install_chain
+};
+
 #[cfg(feature = "install")]
 use crate::{
     installer::{
-        downloader::{Downloader, DownloadingError},
-        installer::Update as InstallerUpdate,
+        diff::{
+            InstallableDiff,
+            PatchResult,
+            verify_checksum,
+            verify_split_checksum,
+            select_temp_folder,
+            is_complete_archive,
+            verify_patched_file,
+            extract_archive
+        },
+        downloader::DownloadingError,
+        installer::{Update as InstallerUpdate, remove_temp},
         free_space,
-        archives::Archive
+        archives::{self, Archive, UnsupportedArchive},
+        options::InstallOptions,
+        report::{InstallReport, FreeSpaceUsage},
+        plan::InstallPlan,
+        manifest::{self, HdiffState},
+        patcher::PatchDecision,
+        transaction::Transaction
     },
-    external::hpatchz
+    repairer::IntegrityFile
 };
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum DiffUpdate {
-    CheckingFreeSpace(PathBuf),
-
-    InstallerUpdate(InstallerUpdate),
-
-    ApplyingHdiffStarted,
-    ApplyingHdiffProgress(u64, u64),
-    ApplyingHdiffFinished,
-
-    RemovingOutdatedStarted,
-    RemovingOutdatedProgress(u64, u64),
-    RemovingOutdatedFinished
-}
-
-impl From<InstallerUpdate> for DiffUpdate {
-    #[inline]
-    fn from(update: InstallerUpdate) -> Self {
-        Self::InstallerUpdate(update)
-    }
-}
-
-#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub enum DiffDownloadingError {
-    /// Your installation is already up to date and not needed to be updated
-    #[error("Component version is already latest")]
-    AlreadyLatest,
-
-    /// Current version is too outdated and can't be updated.
-    /// It means that you have to download everything from zero
-    #[error("Components version is too outdated and can't be updated")]
-    Outdated,
-
-    /// When there's multiple urls and you can't save them as a single file
-    #[error("Component has multiple downloading urls and can't be saved as a single file")]
-    MultipleSegments,
-
-    /// Failed to fetch remove data. Redirected from `Downloader`
-    #[error("{0}")]
-    DownloadingError(#[from] DownloadingError),
-
-    /// Failed to apply hdiff patch
-    #[error("Failed to apply hdiff patch: {0}")]
-    HdiffPatch(String),
-
-    /// Installation path wasn't specified. This could happen when you
-    /// try to call `install` method on `VersionDiff` that was generated
-    /// in `VoicePackage::list_latest`. This method couldn't know
-    /// your game installation path and thus indicates that it doesn't know
-    /// where this package needs to be installed
-    #[error("Path to the component's downloading folder is not specified")]
-    PathNotSpecified
-}
+/// Component the difference was calculated for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiffKind {
+    /// Main game files
+    #[default]
+    Game,
 
-impl From<minreq::Error> for DiffDownloadingError {
-    fn from(error: minreq::Error) -> Self {
-        DownloadingError::Minreq(error.to_string()).into()
-    }
+    /// Voice package of the specified language
+    VoicePackage(VoiceLocale)
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -85,7 +66,10 @@ pub enum VersionDiff {
     /// Latest version
     Latest {
         version: Version,
-        edition: GameEdition
+        edition: GameEdition,
+
+        #[serde(default)]
+        kind: DiffKind
     },
 
     /// Component's update can be predownloaded, but you still can use it
@@ -96,6 +80,12 @@ pub enum VersionDiff {
         uri: String,
         edition: GameEdition,
 
+        #[serde(default)]
+        kind: DiffKind,
+
+        /// MD5 hash of the downloaded archive. Verification is skipped if it's `None`
+        checksum: Option<String>,
+
         downloaded_size: u64,
         unpacked_size: u64,
 
@@ -119,6 +109,12 @@ pub enum VersionDiff {
         uri: String,
         edition: GameEdition,
 
+        #[serde(default)]
+        kind: DiffKind,
+
+        /// MD5 hash of the downloaded archive. Verification is skipped if it's `None`
+        checksum: Option<String>,
+
         downloaded_size: u64,
         unpacked_size: u64,
 
@@ -138,7 +134,10 @@ pub enum VersionDiff {
     Outdated {
         current: Version,
         latest: Version,
-        edition: GameEdition
+        edition: GameEdition,
+
+        #[serde(default)]
+        kind: DiffKind
     },
 
     /// Component is not yet installed
@@ -147,6 +146,12 @@ pub enum VersionDiff {
         segments_uris: Vec<String>,
         edition: GameEdition,
 
+        #[serde(default)]
+        kind: DiffKind,
+
+        /// MD5 hashes of the downloaded segments. Verification is skipped if it's `None`
+        segments_checksums: Option<Vec<String>>,
+
         downloaded_size: u64,
         unpacked_size: u64,
 
@@ -164,6 +169,214 @@ pub enum VersionDiff {
 }
 
 impl VersionDiff {
+    /// Check if the installed version is the latest one
+    ///
+    /// ```
+    /// use anime_game_core::prelude::Version;
+    /// use anime_game_core::star_rail::prelude::*;
+    ///
+    /// let diff = VersionDiff::Latest {
+    ///     version: Version::new(1, 2, 3),
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game
+    /// };
+    ///
+    /// assert!(diff.is_up_to_date());
+    /// assert!(!diff.is_installable());
+    /// ```
+    pub fn is_up_to_date(&self) -> bool {
+        matches!(self, Self::Latest { .. })
+    }
+
+    /// Check if this difference can be installed by the `install`-like methods
+    ///
+    /// ```
+    /// use anime_game_core::prelude::Version;
+    /// use anime_game_core::star_rail::prelude::*;
+    ///
+    /// let diff = VersionDiff::Outdated {
+    ///     current: Version::new(1, 0, 0),
+    ///     latest: Version::new(1, 2, 3),
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game
+    /// };
+    ///
+    /// assert!(!diff.is_installable());
+    /// assert!(diff.requires_full_download());
+    /// ```
+    pub fn is_installable(&self) -> bool {
+        match self {
+            // Can't be installed
+            Self::Latest { .. } |
+            Self::Outdated { .. } => false,
+
+            // Can be installed
+            Self::Predownload { .. } |
+            Self::Diff { .. } |
+            Self::NotInstalled { .. } => true
+        }
+    }
+
+    /// Check if the whole component must be downloaded instead of a difference
+    ///
+    /// ```
+    /// use anime_game_core::prelude::Version;
+    /// use anime_game_core::star_rail::prelude::*;
+    ///
+    /// let diff = VersionDiff::NotInstalled {
+    ///     latest: Version::new(1, 2, 3),
+    ///     segments_uris: vec![String::from("https://example.com/game.zip")],
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game,
+    ///     segments_checksums: None,
+    ///     downloaded_size: 0,
+    ///     unpacked_size: 0,
+    ///     installation_path: None,
+    ///     version_file_path: None,
+    ///     temp_folder: None
+    /// };
+    ///
+    /// assert!(diff.is_installable());
+    /// assert!(diff.requires_full_download());
+    /// ```
+    pub fn requires_full_download(&self) -> bool {
+        matches!(self, Self::NotInstalled { .. } | Self::Outdated { .. })
+    }
+
+    /// Count game releases between the installed and the latest versions
+    ///
+    /// Version numbers don't tell how many releases were made (e.g. 4.8 is followed by 5.0),
+    /// so releases are taken from the `versions` list, which is usually
+    /// made from the `Game::list_versions` output. Return `None` if the component is not installed
+    ///
+    /// ```
+    /// use anime_game_core::prelude::Version;
+    /// use anime_game_core::star_rail::prelude::*;
+    ///
+    /// let versions = [
+    ///     Version::new(5, 1, 0),
+    ///     Version::new(5, 0, 0),
+    ///     Version::new(4, 8, 0),
+    ///     Version::new(4, 7, 0)
+    /// ];
+    ///
+    /// let diff = VersionDiff::Outdated {
+    ///     current: Version::new(4, 7, 0),
+    ///     latest: Version::new(5, 1, 0),
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game
+    /// };
+    ///
+    /// assert_eq!(diff.versions_behind(&versions), Some(3));
+    ///
+    /// let diff = VersionDiff::Outdated {
+    ///     current: Version::new(4, 8, 0),
+    ///     latest: Version::new(5, 0, 0),
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game
+    /// };
+    ///
+    /// assert_eq!(diff.versions_behind(&versions), Some(1));
+    /// ```
+    pub fn versions_behind(&self, versions: &[Version]) -> Option<u32> {
+        match self {
+            // Predownloaded update is not released yet
+            Self::Latest { .. } |
+            Self::Predownload { .. } => Some(0),
+
+            Self::Diff { current, latest, .. } |
+            Self::Outdated { current, latest, .. } => {
+                let releases = versions.iter()
+                    .filter(|version| *version > current && *version <= latest)
+                    .count();
+
+                Some(releases as u32)
+            }
+
+            Self::NotInstalled { .. } => None
+        }
+    }
+
+    /// Get component the difference was calculated for
+    ///
+    /// ```
+    /// use anime_game_core::prelude::Version;
+    /// use anime_game_core::star_rail::prelude::*;
+    ///
+    /// let diff = VersionDiff::Latest {
+    ///     version: Version::new(1, 2, 3),
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game
+    /// };
+    ///
+    /// assert_eq!(diff.kind(), DiffKind::Game);
+    /// ```
+    pub fn kind(&self) -> DiffKind {
+        match self {
+            Self::Latest { kind, .. } |
+            Self::Predownload { kind, .. } |
+            Self::Diff { kind, .. } |
+            Self::Outdated { kind, .. } |
+            Self::NotInstalled { kind, .. } => *kind
+        }
+    }
+
+    /// Get url of the archive this difference is downloaded from
+    ///
+    /// Return url of the first segment if the archive is split into several ones
+    ///
+    /// ```
+    /// use anime_game_core::prelude::Version;
+    /// use anime_game_core::star_rail::prelude::*;
+    ///
+    /// let diff = VersionDiff::NotInstalled {
+    ///     latest: Version::new(1, 0, 0),
+    ///     segments_uris: vec![
+    ///         String::from("https://example.com/game.zip.001"),
+    ///         String::from("https://example.com/game.zip.002")
+    ///     ],
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game,
+    ///     segments_checksums: None,
+    ///     downloaded_size: 0,
+    ///     unpacked_size: 0,
+    ///     installation_path: None,
+    ///     version_file_path: None,
+    ///     temp_folder: None
+    /// };
+    ///
+    /// assert_eq!(diff.download_url(), Some("https://example.com/game.zip.001"));
+    /// assert_eq!(diff.download_urls().map(|urls| urls.len()), Some(2));
+    ///
+    /// let diff = VersionDiff::Latest {
+    ///     version: Version::new(1, 0, 0),
+    ///     edition: GameEdition::Global,
+    ///     kind: DiffKind::Game
+    /// };
+    ///
+    /// assert_eq!(diff.download_url(), None);
+    /// ```
+    pub fn download_url(&self) -> Option<&str> {
+        self.download_urls()
+            .and_then(|urls| urls.first())
+            .map(String::as_str)
+    }
+
+    /// Get urls of all the archive segments this difference is downloaded from
+    pub fn download_urls(&self) -> Option<&[String]> {
+        match self {
+            // Can't be installed
+            Self::Latest { .. } |
+            Self::Outdated { .. } => None,
+
+            // Can be installed
+            Self::Predownload { uri, .. } |
+            Self::Diff { uri, .. } => Some(std::slice::from_ref(uri)),
+
+            Self::NotInstalled { segments_uris, .. } => Some(segments_uris)
+        }
+    }
+
     /// Get `.version` file path
     pub fn version_file_path(&self) -> Option<PathBuf> {
         match self {
@@ -223,169 +436,270 @@ impl VersionDiff {
             }
         }
     }
-}
 
-impl VersionDiffExt for VersionDiff {
-    type Error = DiffDownloadingError;
-    type Update = DiffUpdate;
-    type Edition = GameEdition;
+    /// Specify path to the folder this difference should be installed by the `install` method
+    ///
+    /// Useful for differences returned by `VoicePackage::list_latest` which don't know game installation path.
+    /// Does nothing for `Latest` and `Outdated` variants
+    pub fn with_installation_path(mut self, path: PathBuf) -> Self {
+        match &mut self {
+            // Can't be installed
+            Self::Latest { .. } |
+            Self::Outdated { .. } => self,
 
-    fn edition(&self) -> GameEdition {
-        match self {
-            Self::Latest { edition, .. } |
-            Self::Predownload { edition, .. } |
-            Self::Diff { edition, .. } |
-            Self::Outdated { edition, .. } |
-            Self::NotInstalled { edition, .. } => *edition
+            // Can be installed
+            Self::Predownload { installation_path, .. } |
+            Self::Diff { installation_path, .. } |
+            Self::NotInstalled { installation_path, .. } => {
+                *installation_path = Some(path);
+
+                self
+            }
         }
     }
 
-    fn current(&self) -> Option<Version> {
-        match self {
-            Self::Latest { version: current, .. } |
-            Self::Predownload { current, .. } |
-            Self::Diff { current, .. } |
-            Self::Outdated { current, .. } => Some(*current),
+    #[cfg(feature = "install")]
+    /// Describe what installation of this difference is going to do without making any changes
+    ///
+    /// Patched and removed files are listed only if the installation manifests
+    /// are already present in the installation folder
+    pub fn plan(&self) -> Result<InstallPlan, DiffDownloadingError> {
+        let uris = match self {
+            Self::Latest { .. } => return Err(DiffDownloadingError::AlreadyLatest),
+            Self::Outdated { .. } => return Err(DiffDownloadingError::Outdated),
 
-            Self::NotInstalled { .. } => None
+            Self::Predownload { uri, .. } |
+            Self::Diff { uri, .. } => vec![uri.to_owned()],
+
+            Self::NotInstalled { segments_uris, .. } => segments_uris.to_owned()
+        };
+
+        let mut plan = InstallPlan {
+            uris,
+            downloaded_size: self.downloaded_size().unwrap_or_default(),
+            unpacked_size: self.unpacked_size().unwrap_or_default(),
+            ..InstallPlan::default()
+        };
+
+        // Not installed component can't have any files to patch or remove
+        if let Self::Predownload { installation_path: Some(path), .. } | Self::Diff { installation_path: Some(path), .. } = self {
+            if let Ok(files) = manifest::read_hdiff_files(path) {
+                plan.patched_files = files.into_iter()
+                    .map(|file| path.join(file))
+                    .collect();
+            }
+
+            if let Ok(files) = manifest::read_delete_files(path) {
+                plan.freed_size = manifest::existing_files_size(&files);
+                plan.deleted_files = files;
+            }
         }
+
+        Ok(plan)
     }
 
-    fn latest(&self) -> Version {
-        match self {
-            Self::Latest { version: latest, .. } |
-            Self::Predownload { latest, .. } |
-            Self::Diff { latest, .. } |
-            Self::Outdated { latest, .. } |
-            Self::NotInstalled { latest, .. } => *latest
-        }
+    #[cfg(feature = "install")]
+    /// Try to install the difference by given location using specified options
+    ///
+    /// Return summary of the changes made to the installation folder
+    ///
+    /// In the transactional mode all the changed files are restored if installation fails or is cancelled
+    pub fn install_to_by(&self, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError> {
+        self.install_by(path, options, updater, None)
     }
 
-    fn downloaded_size(&self) -> Option<u64> {
-        match self {
-            // Can't be installed
-            Self::Latest { .. } |
-            Self::Outdated { .. } => None,
+    #[cfg(feature = "install")]
+    /// Install the archive downloaded by the `download_archive_to` method
+    ///
+    /// Downloading is skipped, so the archive is verified and extracted right away.
+    /// Like with the `install_to_by` method, the archive is removed after the installation.
+    /// It's removed as well if its checksum doesn't match, so it must be downloaded again
+    pub fn install_downloaded(&self, archive: &DownloadedArchive, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError> {
+        let checksum = match self {
+            Self::Latest { .. } => return Err(DiffDownloadingError::AlreadyLatest),
+            Self::Outdated { .. } => return Err(DiffDownloadingError::Outdated),
+            Self::NotInstalled { .. } => return Err(DiffDownloadingError::MultipleSegments),
+
+            Self::Predownload { checksum, .. } |
+            Self::Diff { checksum, .. } => checksum
+        };
 
-            // Can be installed
-            Self::Predownload { downloaded_size, .. } |
-            Self::Diff { downloaded_size, .. } |
-            Self::NotInstalled { downloaded_size, .. } => Some(*downloaded_size)
+        if archive.version != self.latest() {
+            return Err(DiffDownloadingError::ArchiveVersionMismatch(archive.path.clone(), archive.version, self.latest()));
         }
-    }
-
-    fn unpacked_size(&self) -> Option<u64> {
-        match self {
-            // Can't be installed
-            Self::Latest { .. } |
-            Self::Outdated { .. } => None,
 
-            // Can be installed
-            Self::Predownload { unpacked_size, .. } |
-            Self::Diff { unpacked_size, .. } |
-            Self::NotInstalled { unpacked_size, .. } => Some(*unpacked_size)
+        if !archive.path.is_file() {
+            return Err(DownloadingError::OutputFileError(archive.path.clone(), String::from("downloaded archive doesn't exist")).into());
         }
-    }
 
-    fn installation_path(&self) -> Option<&Path> {
-        match self {
-            // Can't be installed
-            Self::Latest { .. } |
-            Self::Outdated { .. } => None,
+        // Checksum from the API is preferred if the archive was downloaded without it
+        verify_checksum(&archive.path, checksum.as_deref().or(archive.checksum.as_deref()))?;
 
-            // Can be installed
-            Self::Predownload { installation_path, .. } |
-            Self::Diff { installation_path, .. } |
-            Self::NotInstalled { installation_path, .. } => match installation_path {
-                Some(path) => Some(path.as_path()),
-                None => None
-            }
-        }
+        self.install_by(path, options, updater, Some(archive))
     }
 
-    fn downloading_uri(&self) -> Option<String> {
-        match self {
-            // Can't be installed
-            Self::Latest { .. } |
-            Self::Outdated { .. } => None,
+    #[cfg(feature = "install")]
+    /// Install the difference downloading its archives unless they're already downloaded
+    fn install_by(&self, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static, downloaded: Option<&DownloadedArchive>) -> Result<InstallReport, DiffDownloadingError> {
+        let mut archives = Vec::new();
 
-            // Can be installed
-            Self::Predownload { uri, .. } |
-            Self::Diff { uri, .. } => Some(uri.to_owned()),
+        let mut result = if options.transactional {
+            self.install_transactional(path, options, updater, &mut archives, downloaded)
+        } else {
+            self.install_in_transaction(path, options, updater, &mut archives, None, downloaded)
+        };
 
-            // Can be installed but amogus
-            Self::NotInstalled { .. } => None
+        // Extracted archives are kept if installation failed after their extraction,
+        // so the next installation can reuse them if their checksums are known
+        let has_checksums = match self {
+            Self::Predownload { checksum, .. } |
+            Self::Diff { checksum, .. } => checksum.as_ref().is_some_and(|checksum| !checksum.is_empty()),
+
+            // Segments without checksums can't be reused
+            Self::NotInstalled { segments_checksums, .. } => segments_checksums.as_ref()
+                .is_some_and(|checksums| !checksums.is_empty() && checksums.iter().all(|checksum| !checksum.is_empty())),
+
+            _ => false
+        };
+
+        let keep = result.is_err() && (has_checksums || options.keep_temp_on_error);
+
+        for archive in archives {
+            remove_temp(&archive, keep);
         }
+
+        // Measured after the temp files removal to show space used by the installation itself
+        if let Ok(report) = &mut result {
+            for space in [&mut report.install_free_space, &mut report.temp_free_space].into_iter().flatten() {
+                space.update();
+            }
+        }
+
+        result
     }
 
-    fn download_as(&mut self, path: impl AsRef<Path>, progress: impl Fn(u64, u64) + Send + 'static) -> Result<(), Self::Error> {
-        tracing::debug!("Downloading version difference");
+    #[cfg(feature = "install")]
+    /// Install the difference restoring all the changed files if installation fails
+    fn install_transactional(&self, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static, archives: &mut Vec<PathBuf>, downloaded: Option<&DownloadedArchive>) -> Result<InstallReport, DiffDownloadingError> {
+        let temp_folder = options.temp_folder.clone()
+            .unwrap_or_else(|| self.temp_folder());
 
-        let mut downloader = Downloader::new(match self {
-            // Can't be downloaded
-            Self::Latest { .. } => return Err(Self::Error::AlreadyLatest),
-            Self::Outdated { .. } => return Err(Self::Error::Outdated),
+        let mut transaction = Transaction::new(temp_folder.join(".anime-game-core-backup"));
 
-            // Can be downloaded
-            Self::Predownload { uri, .. } |
-            Self::Diff { uri, .. } => uri,
+        match self.install_in_transaction(path, options, updater, archives, Some(&mut transaction), downloaded) {
+            Ok(report) => {
+                let backup_folder = transaction.backup_folder().to_path_buf();
 
-            // Can be installed but amogus
-            Self::NotInstalled { .. } => return Err(Self::Error::MultipleSegments)
-        })?;
+                if let Err(err) = transaction.commit() {
+                    return Err(DiffDownloadingError::FileRemoveFailed(backup_folder, err.to_string()));
+                }
 
-        if let Err(err) = downloader.download(path.as_ref(), progress) {
-            tracing::error!("Failed to download version difference: {err}");
+                Ok(report)
+            }
 
-            return Err(err.into());
-        }
+            Err(err) => {
+                tracing::warn!("Installation failed. Restoring changed files");
 
-        Ok(())
+                if let Err(rollback_err) = transaction.rollback() {
+                    tracing::error!("Failed to restore changed files: {rollback_err}");
+                }
+
+                Err(err)
+            }
+        }
     }
 
-    fn install_to(&self, path: impl AsRef<Path>, updater: impl Fn(Self::Update) + Clone + Send + 'static) -> Result<(), Self::Error> {
+    #[cfg(feature = "install")]
+    /// Install the difference saving files to the transaction before changing them
+    ///
+    /// Successfully extracted archives are added to the `archives` instead of being removed.
+    /// Already downloaded archive is used instead of downloading the difference if specified
+    fn install_in_transaction(&self, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static, archives: &mut Vec<PathBuf>, mut transaction: Option<&mut Transaction>, downloaded: Option<&DownloadedArchive>) -> Result<InstallReport, DiffDownloadingError> {
         tracing::debug!("Installing version difference");
 
-        let uris = match self {
+        // Files are journaled before changing them so they can't be changed while downloading
+        let mut backup = |file: PathBuf| -> Result<(), DiffDownloadingError> {
+            match transaction.as_mut().map(|transaction| transaction.backup(&file)) {
+                Some(Err(err)) => Err(DiffDownloadingError::BackupFailed(file, err.to_string())),
+                _ => Ok(())
+            }
+        };
+
+        let mut report = InstallReport::default();
+
+        let (uris, checksums) = match self {
             // Can't be installed
-            Self::Latest { .. } => return Err(Self::Error::AlreadyLatest),
-            Self::Outdated { .. } => return Err(Self::Error::Outdated),
+            Self::Latest { .. } => return Err(DiffDownloadingError::AlreadyLatest),
+            Self::Outdated { .. } => return Err(DiffDownloadingError::Outdated),
 
             // Can be installed
-            Self::Predownload { uri, .. } |
-            Self::Diff { uri, .. } => vec![uri.to_owned()],
+            Self::Predownload { uri, checksum, .. } |
+            Self::Diff { uri, checksum, .. } => (vec![uri.to_owned()], vec![checksum.to_owned()]),
 
-            Self::NotInstalled { segments_uris, .. } => segments_uris.to_owned()
+            Self::NotInstalled { segments_uris, segments_checksums, .. } => {
+                let checksums = segments_checksums.iter()
+                    .flatten()
+                    .map(|checksum| Some(checksum.to_owned()))
+                    .collect();
+
+                (segments_uris.to_owned(), checksums)
+            }
+        };
+
+        // Checksum of the archive split into parts by the mirror
+        let mut split_checksum = None;
+
+        // Mirrors can serve big archives split into `.001`, `.002`, ... parts
+        let (uris, checksums) = match uris.as_slice() {
+            // Nothing to download
+            _ if downloaded.is_some() => (Vec::new(), Vec::new()),
+
+            [uri] if uri.ends_with(".001") => {
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::CheckingSize(uri.to_owned())));
+
+                let parts = options.downloader(uri)?.split_parts()?;
+
+                if parts.len() > 1 {
+                    tracing::debug!("Found {} parts of the split archive", parts.len());
+
+                    // Checksum is known only for the whole archive, so parts are verified together after downloading
+                    split_checksum = checksums.into_iter().next().flatten();
+
+                    (parts.into_iter().map(|(uri, _)| uri).collect(), Vec::new())
+                } else {
+                    (uris, checksums)
+                }
+            }
+
+            _ => (uris, checksums)
         };
 
         let path = path.as_ref().to_path_buf();
-        let temp_folder = self.temp_folder();
+
+        let temp_folder = options.temp_folder.clone()
+            .unwrap_or_else(|| self.temp_folder());
 
         let downloaded_size = self.downloaded_size().expect("Failed to retreive downloaded size");
         let unpacked_size = self.unpacked_size().expect("Failed to retreive unpacked size");
 
-        (updater)(DiffUpdate::CheckingFreeSpace(temp_folder.clone()));
+        // Streamed archive is not stored in the temp folder. It can't be verified
+        // before its extraction, so archives with known checksums are downloaded first
+        let has_checksum = checksums.iter()
+            .flatten()
+            .any(|checksum| !checksum.is_empty());
 
-        // Check available free space for archive itself
-        let Some(space) = free_space::available(&temp_folder) else {
-            tracing::error!("Path is not mounted: {:?}", temp_folder);
+        let streamed = options.stream_extract && !options.transactional && !options.skip_identical && !has_checksum && options.extract_prefixes.is_none() && uris.len() == 1 && archives::is_streamable(&uris[0]);
 
-            return Err(DownloadingError::PathNotMounted(temp_folder).into());
-        };
-
-        // We can possibly store downloaded archive + unpacked data on the same disk
-        let required = if free_space::is_same_disk(&temp_folder, &path) {
-            downloaded_size + unpacked_size
+        let temp_folder = if let Some(archive) = downloaded {
+            archive.path.parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default()
+        } else if streamed {
+            temp_folder
         } else {
-            downloaded_size
+            select_temp_folder(temp_folder, &path, downloaded_size, unpacked_size, options, &updater)?
         };
 
-        if space < required {
-            tracing::error!("No free space available in the temp folder. Required: {required}. Available: {space}");
-
-            return Err(DownloadingError::NoSpaceAvailable(temp_folder, required, space).into());
-        }
-
         (updater)(DiffUpdate::CheckingFreeSpace(path.clone()));
 
         // Check available free space for unpacked archvie data
@@ -396,7 +710,7 @@ impl VersionDiffExt for VersionDiff {
         };
 
         // We can possibly store downloaded archive + unpacked data on the same disk
-        let required = if free_space::is_same_disk(&path, &temp_folder) {
+        let required = if !streamed && downloaded.is_none() && free_space::is_same_disk(&path, &temp_folder) {
             unpacked_size + downloaded_size
         } else {
             unpacked_size
@@ -408,261 +722,1233 @@ impl VersionDiffExt for VersionDiff {
             return Err(DownloadingError::NoSpaceAvailable(path.to_path_buf(), required, space).into());
         }
 
-        let mut current_downloaded = 0;
-        let mut segments_names = Vec::new();
+        report.install_free_space = Some(FreeSpaceUsage {
+            path: path.clone(),
+            before: space,
+            after: space
+        });
 
-        // Imitate Installer update message
-        (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingStarted(temp_folder.to_path_buf())));
+        report.temp_free_space = FreeSpaceUsage::measure(&temp_folder);
 
-        // Download segments
-        for uri in uris {
-            let installer_updater = updater.clone();
+        let streamed = streamed && self.install_streamed(&uris[0], &path, options, &updater, &mut report)?;
 
-            let mut downloader = Downloader::new(uri)?
-                // Don't perform space checks because we've already done it
-                .with_free_space_check(false);
+        // Download segments and extract them if the archive wasn't streamed
+        if !streamed {
+            let mut current_downloaded = 0;
+            let mut segments_names = Vec::new();
 
-            let local_total = downloader.length().unwrap();
-            let segment_name = downloader.get_filename().to_string();
+            if let Some(name) = downloaded.and_then(|archive| archive.path.file_name()) {
+                segments_names.push(name.to_string_lossy().to_string());
 
-            // Download segment
-            downloader.download(temp_folder.join(&segment_name), move |current, _| {
-                (installer_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(
-                    current_downloaded + current,
-                    downloaded_size
-                )));
-            })?;
+                current_downloaded = downloaded_size;
+            }
 
-            segments_names.push(segment_name);
+            // Imitate Installer update message
+            (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingStarted(temp_folder.to_path_buf())));
 
-            current_downloaded += local_total;
-        }
+            // Total size is already known from the API
+            (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(0, downloaded_size)));
 
-        // Report 100% download progress (just in case)
-        (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(downloaded_size, downloaded_size)));
+            // Download segments
+            for (i, uri) in uris.into_iter().enumerate() {
+                let installer_updater = updater.clone();
 
-        let first_segment_name = segments_names[0].clone();
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::CheckingSize(uri.clone())));
 
-        // Imitate Installer update message
-        (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingFinished));
+                let mut downloader = options.downloader(uri)?
+                    // Don't perform space checks because we've already done it
+                    .with_free_space_check(false);
 
-        // Extract downloaded segments
-        // Ctrl+C / Ctrl+V from the Installer. Not a good approach,
-        // but current core library is somehow legacy as I already started work
-        // on a full rewrite so this code won't stay here for always
-        match Archive::open(temp_folder.join(&first_segment_name)) {
-            Ok(mut archive) => {
-                // Temporary workaround as we can't get archive extraction process
-                // directly - we'll spawn it in another thread and check this archive entries appearance in the filesystem
-                let mut total = 0;
+                // Length is unknown if the server rejects HEAD requests and ignores the range header
+                let local_total = downloader.length();
+                let segment_name = downloader.get_filename().to_string();
 
-                let entries = archive
-                    .get_entries()
-                    .expect("Failed to get archive entries");
+                let checksum = checksums.get(i).cloned().flatten();
 
-                for entry in &entries {
-                    total += entry.size.get_size();
+                // Archive could be kept by the previous installation which failed after its extraction
+                let reusable = local_total.is_some_and(|total| {
+                    is_complete_archive(&temp_folder.join(&segment_name), total, checksum.as_deref())
+                });
 
-                    let path = path.join(&entry.name);
+                if reusable {
+                    tracing::info!("Reusing already downloaded archive {:?}", temp_folder.join(&segment_name));
 
-                    // Failed to change permissions => likely patch-related file and was made by the sudo, so root
-                    #[allow(unused_must_use)]
-                    if std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).is_err() {
-                        // For weird reason we can delete files made by root, but can't modify their permissions
-                        // We're not checking its result because if it's error - then it's either couldn't be removed (which is not the case)
-                        // or the file doesn't exist, which we obviously can just ignore
-                        std::fs::remove_file(&path);
-                    }
-                }
+                    segments_names.push(segment_name);
 
-                tracing::trace!("Extracting archive");
+                    current_downloaded += local_total.unwrap_or_default();
 
-                let unpacking_path = path.clone();
-                let unpacking_updater = updater.clone();
+                    (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(current_downloaded, downloaded_size)));
 
-                let handle_2 = std::thread::spawn(move || {
-                    let mut entries = entries.into_iter()
-                        .map(|entry| (unpacking_path.join(&entry.name), entry.size.get_size(), true))
-                        .collect::<Vec<_>>();
+                    continue;
+                }
 
-                    let mut unpacked = 0;
+                // Download segment
+                let result = downloader.download(temp_folder.join(&segment_name), move |current, _| {
+                    (installer_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(
+                        current_downloaded + current,
+                        downloaded_size
+                    )));
+                });
 
-                    loop {
-                        std::thread::sleep(std::time::Duration::from_millis(250));
+                match result {
+                    Ok(()) => (),
 
-                        let mut empty = true;
+                    Err(DownloadingError::Cancelled) => {
+                        (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::Cancelled));
 
-                        for (path, size, remained) in &mut entries {
-                            if *remained {
-                                empty = false;
+                        return Err(DiffDownloadingError::Cancelled);
+                    }
 
-                                if std::path::Path::new(path).exists() {
-                                    *remained = false;
+                    Err(err) => return Err(err.into())
+                }
+
+                if let Err(err) = verify_checksum(&temp_folder.join(&segment_name), checksum.as_deref()) {
+                    remove_temp(&temp_folder.join(&segment_name), options.keep_temp_on_error);
+
+                    return Err(err);
+                }
+
+                report.downloads.extend(downloader.download_info());
+
+                current_downloaded += local_total.unwrap_or_else(|| {
+                    temp_folder.join(&segment_name).metadata()
+                        .map(|metadata| metadata.len())
+                        .unwrap_or_default()
+                });
+
+                segments_names.push(segment_name);
+            }
+
+            let segments = segments_names.iter()
+                .map(|name| temp_folder.join(name))
+                .collect::<Vec<_>>();
+
+            if let Err(err) = verify_split_checksum(&segments, split_checksum.as_deref()) {
+                for segment in &segments {
+                    remove_temp(segment, options.keep_temp_on_error);
+                }
+
+                return Err(err);
+            }
+
+            // Report 100% download progress (just in case)
+            (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(downloaded_size, downloaded_size)));
+
+            let first_segment_name = segments_names[0].clone();
 
-                                    unpacked += *size;
-                                }
+            // Imitate Installer update message
+            (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingFinished));
+
+            if options.is_cancelled() {
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::Cancelled));
+
+                return Err(DiffDownloadingError::Cancelled);
+            }
+
+            // Extract downloaded segments
+            // Ctrl+C / Ctrl+V from the Installer. Not a good approach,
+            // but current core library is somehow legacy as I already started work
+            // on a full rewrite so this code won't stay here for always
+
+            // Make sure the archive can be extracted before changing anything in the installation folder
+            let entries = Archive::open(temp_folder.join(&first_segment_name))
+                .and_then(|mut archive| archive.get_entries());
+
+            let mut entries = match entries {
+                Ok(entries) => entries,
+
+                Err(err) => {
+                    for name in &segments_names {
+                        remove_temp(&temp_folder.join(name), options.keep_temp_on_error);
+                    }
+
+                    if let Some(UnsupportedArchive(archive)) = err.downcast_ref() {
+                        return Err(DiffDownloadingError::UnsupportedArchive(archive.to_owned()));
+                    }
+
+                    tracing::error!("Downloaded archive is corrupted: {err}");
+
+                    return Err(DiffDownloadingError::CorruptArchive(temp_folder.join(first_segment_name), err.to_string()));
+                }
+            };
+
+            entries.retain(|entry| options.is_included(&entry.name));
+
+            for entry in &entries {
+                backup(path.join(&entry.name))?;
+            }
+
+            // Temporary workaround as we can't get archive extraction process
+            // directly - we'll spawn it in another thread and check this archive entries appearance in the filesystem
+            let mut total = 0;
+
+            for entry in &entries {
+                total += entry.size.get_size();
+
+                let path = path.join(&entry.name);
+
+                // Failed to change permissions => likely patch-related file and was made by the sudo, so root
+                #[allow(unused_must_use)]
+                if std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).is_err() {
+                    // For weird reason we can delete files made by root, but can't modify their permissions
+                    // We're not checking its result because if it's error - then it's either couldn't be removed (which is not the case)
+                    // or the file doesn't exist, which we obviously can just ignore
+                    std::fs::remove_file(&path);
+                }
+            }
+
+            tracing::trace!("Extracting archive");
+
+            let unpacking_path = path.clone();
+            let unpacking_updater = updater.clone();
+
+            // Set when extraction is finished, even if it failed, to stop progress reporting
+            let unpacking_finished = Arc::new(AtomicBool::new(false));
+            let unpacking_finished_flag = unpacking_finished.clone();
+
+            let handle_2 = std::thread::spawn(move || {
+                let mut entries = entries.into_iter()
+                    .map(|entry| (unpacking_path.join(&entry.name), entry.size.get_size(), true))
+                    .collect::<Vec<_>>();
+
+                let mut unpacked = 0;
+
+                loop {
+                    std::thread::sleep(std::time::Duration::from_millis(250));
+
+                    let mut empty = true;
+
+                    for (path, size, remained) in &mut entries {
+                        if *remained {
+                            empty = false;
+
+                            if std::path::Path::new(path).exists() {
+                                *remained = false;
+
+                                unpacked += *size;
                             }
                         }
+                    }
 
-                        (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingProgress(unpacked, total)));
+                    if empty || unpacking_finished_flag.load(Ordering::Relaxed) {
+                        break;
+                    }
 
-                        if empty {
-                            break;
+                    (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingProgress(unpacked, total)));
+                }
+            });
+
+            let unpacking_updater = updater.clone();
+            let extract_to = path.clone();
+
+            let keep_temp_on_error = options.keep_temp_on_error;
+            let extract_options = options.clone();
+
+            let segments_paths = segments_names.iter()
+                .map(|name| temp_folder.join(name))
+                .collect::<Vec<_>>();
+
+            // Run archive extraction in another thread to not to freeze the current one
+            let handle_1 = std::thread::spawn(move || {
+                (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingStarted(extract_to.clone())));
+
+                // We have to create new instance of Archive here
+                // because otherwise it may not work after get_entries method call
+                match Archive::open(temp_folder.join(first_segment_name)) {
+                    Ok(mut archive) => match extract_archive(&mut archive, &extract_to, &extract_options) {
+                        Ok(_) => {
+                            unpacking_finished.store(true, Ordering::Relaxed);
+
+                            (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingProgress(total, total)));
+                            (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingFinished));
+
+                            Ok(())
                         }
-                    }
-                });
 
-                let unpacking_updater = updater.clone();
-                let extract_to = path.clone();
-
-                // Run archive extraction in another thread to not to freeze the current one
-                let handle_1 = std::thread::spawn(move || {
-                    (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingStarted(extract_to.clone())));
-
-                    // We have to create new instance of Archive here
-                    // because otherwise it may not work after get_entries method call
-                    match Archive::open(temp_folder.join(first_segment_name)) {
-                        Ok(mut archive) => match archive.extract(&extract_to) {
-                            Ok(_) => {
-                                // TODO error handling
-                                #[allow(unused_must_use)] {
-                                    for name in segments_names {
-                                        std::fs::remove_file(temp_folder.join(name));
-                                    }
-                                }
-
-                                (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingFinished));
+                        Err(err) => {
+                            unpacking_finished.store(true, Ordering::Relaxed);
+
+                            for name in segments_names {
+                                remove_temp(&temp_folder.join(name), keep_temp_on_error);
                             }
 
-                            Err(err) => (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingError(err.to_string())))
+                            (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingError(err.to_string())));
+
+                            Err(err.to_string())
+                        }
+                    }
+
+                    Err(err) => {
+                        unpacking_finished.store(true, Ordering::Relaxed);
+
+                        for name in segments_names {
+                            remove_temp(&temp_folder.join(name), keep_temp_on_error);
                         }
 
-                        Err(err) => (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingError(err.to_string())))
+                        (unpacking_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingError(err.to_string())));
+
+                        Err(err.to_string())
                     }
-                });
+                }
+            });
 
-                handle_1.join().unwrap();
-                handle_2.join().unwrap();
-            }
+            let unpacked = handle_1.join().unwrap();
 
-            Err(err) => (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingError(err.to_string())))
-        }
+            handle_2.join().unwrap();
+
+            match unpacked {
+                Ok(()) => {
+                    report.unpacked_bytes = total;
+
+                    // Archives are removed after the installation is finished
+                    archives.extend(segments_paths);
+                }
 
-        // Imitate Installer update message
-        (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingFinished));
+                // Error makes the transaction restore already extracted files
+                Err(err) => {
+                    tracing::error!("Failed to extract archive: {err}");
+
+                    return Err(DiffDownloadingError::Unpacking(segments_paths[0].clone(), err));
+                }
+            }
+        }
 
         // Create `.version` file here even if hdiff patching is failed because
         // it's easier to explain user why he should run files repairer than
         // why he should re-download entire game update because something is failed
-        #[allow(unused_must_use)] {
-            let version_path = self.version_file_path()
-                .unwrap_or_else(|| path.join(".version"));
+        let version_path = self.version_file_path()
+            .unwrap_or_else(|| path.join(".version"));
+
+        backup(version_path.clone())?;
 
+        #[allow(unused_must_use)] {
             std::fs::write(version_path, self.latest().version);
         }
 
         // Apply hdiff patches
         // We're ignoring Err because in practice it means that hdifffiles.txt is missing
-        if let Ok(files) = std::fs::read_to_string(path.join("hdifffiles.txt")) {
-            tracing::debug!("Applying hdiff patches");
+        if let Ok(mut files) = manifest::read_hdiff_entries(&path) {
+            files.retain(|file| options.is_included(&file.remote_name));
+
+            // Verify files before patching if the manifest has their hashes
+            // because hpatchz will produce broken files from the wrong source ones
+            for file in &files {
+                let Some(expected) = &file.source_md5 else {
+                    continue;
+                };
+
+                // Already patched files have another hash
+                if manifest::hdiff_state(&path, &file.remote_name) != HdiffState::Pending || !manifest::is_safe_path(&path, &file.remote_name) {
+                    continue;
+                }
+
+                let source = path.join(&file.remote_name);
+
+                // Missing files are reported by the patcher
+                if let Ok(checksum) = crate::repairer::file_md5(&source) {
+                    if !checksum.eq_ignore_ascii_case(expected) {
+                        tracing::error!("File {:?} can't be patched. Expected MD5: {expected}. Got: {checksum}", source);
+
+                        return Err(DiffDownloadingError::SourceMismatch(source, expected.to_string(), checksum));
+                    }
+                }
+            }
+
+            for file in &files {
+                if manifest::is_safe_path(&path, &file.remote_name) {
+                    backup(path.join(&file.remote_name))?;
+                    backup(path.join(format!("{}.hdiff_patched", file.remote_name)))?;
+                }
+            }
+
+            let files = files.into_iter()
+                .map(|file| file.remote_name)
+                .collect();
+
+            let patches = self.apply_hdiff_patches_with_failures(&path, files, options, &updater)?;
 
-            (updater)(Self::Update::ApplyingHdiffStarted);
+            report.patched_files = patches.patched_files;
+            report.failed_patches = patches.failed_patches;
+            report.skipped_patches = patches.skipped_patches;
 
-            let files = files.lines().collect::<Vec<&str>>();
-            let hdiffs = files.len() as u64;
+            if let Err(err) = std::fs::remove_file(path.join(manifest::HDIFF_FILES)) {
+                return Err(DiffDownloadingError::FileRemoveFailed(path.join(manifest::HDIFF_FILES), err.to_string()));
+            }
+        }
+
+        if !options.apply_deletefiles {
+            tracing::debug!("Skipping outdated files deletion");
+
+            // Remove the manifest anyway so it won't be applied by the next installation
+            let manifest_path = path.join(manifest::DELETE_FILES);
+
+            if manifest_path.exists() {
+                if let Err(err) = std::fs::remove_file(&manifest_path) {
+                    return Err(DiffDownloadingError::FileRemoveFailed(manifest_path, err.to_string()));
+                }
+            }
+
+            return Ok(report);
+        }
+
+        tracing::debug!("Deleting outdated files");
+
+        // Remove outdated files
+        // We're ignoring Err because in practice it means that deletefiles.txt is missing
+        if let Ok(mut files) = manifest::read_delete_files(&path) {
+            files.retain(|file| options.is_included(file.strip_prefix(&path).unwrap_or(file)));
+
+            let files_len = files.len() as u64;
 
-            // {"remoteName": "AnimeGame_Data/StreamingAssets/Audio/GeneratedSoundBanks/Windows/Japanese/1001.pck"}
+            (updater)(DiffUpdate::RemovingOutdatedStarted);
+
+            for file in &files {
+                if manifest::is_safe_path(&path, file) {
+                    backup(file.to_path_buf())?;
+                }
+            }
+
+            // AnimeGame_Data/Plugins/metakeeper.dll
             for (i, file) in files.into_iter().enumerate() {
-                let relative_file = &file[16..file.len() - 2];
-
-                let file = path.join(relative_file);
-                let patch = path.join(format!("{relative_file}.hdiff"));
-                let output = path.join(format!("{relative_file}.hdiff_patched"));
-
-                // If failed to apply the patch
-                if let Err(err) = hpatchz::patch(&file, &patch, &output) {
-                    tracing::warn!("Failed to apply hdiff patch for {:?}: {err}", file);
-                    tracing::debug!("Trying to repair corrupted file");
-
-                    // If we were able to get API response - it shouldn't be impossible
-                    // to also get integrity files list from the same API
-                    match super::repairer::try_get_integrity_file(self.edition(), relative_file, Some(*crate::REQUESTS_TIMEOUT)) {
-                        Ok(Some(integrity)) => {
-                            if !integrity.fast_verify(&path) {
-                                if let Err(err) = integrity.repair(&path) {
-                                    tracing::error!("Failed to repair corrupted file: {err}");
-
-                                    return Err(err.into());
-                                }
-                            }
+                if options.is_cancelled() {
+                    (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::Cancelled));
+
+                    return Err(DiffDownloadingError::Cancelled);
+                }
+
+                if !manifest::is_safe_path(&path, &file) {
+                    tracing::error!("Outdated file is outside of the installation folder: {:?}", file);
+
+                    return Err(DiffDownloadingError::UnsafePath(file));
+                }
+
+                match manifest::remove_outdated_file(&file) {
+                    Ok(true) => report.deleted_files.push(file),
+
+                    // File could be removed manually or by the previous installation attempt
+                    Ok(false) => {
+                        tracing::warn!("Outdated file doesn't exist: {:?}", file);
+
+                        (updater)(DiffUpdate::RemovingOutdatedSkipped(file));
+                    }
+
+                    Err(err) => {
+                        tracing::error!("Failed to remove outdated file {:?}: {err}", file);
+
+                        return Err(DiffDownloadingError::FileRemoveFailed(file, err.to_string()));
+                    }
+                }
+
+                (updater)(DiffUpdate::RemovingOutdatedProgress(i as u64 + 1, files_len));
+            }
+
+            if let Err(err) = std::fs::remove_file(path.join(manifest::DELETE_FILES)) {
+                return Err(DiffDownloadingError::FileRemoveFailed(path.join(manifest::DELETE_FILES), err.to_string()));
+            }
+
+            (updater)(DiffUpdate::RemovingOutdatedFinished);
+        }
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "install")]
+    /// Apply hdiff patches for the given files relative to the installation folder
+    ///
+    /// Used by the `install_to_by` method with files listed in the `hdifffiles.txt`,
+    /// but the list can also be received from elsewhere. Patches are expected to be
+    /// stored near the patched files with the `.hdiff` extension
+    ///
+    /// Return list of patched files
+    pub fn apply_hdiff_patches(&self, path: impl AsRef<Path>, files: Vec<String>, options: &InstallOptions, updater: impl Fn(DiffUpdate)) -> Result<Vec<PathBuf>, DiffDownloadingError> {
+        self.apply_hdiff_patches_with_failures(path, files, options, updater)
+            .map(|report| report.patched_files)
+    }
+
+    #[cfg(feature = "install")]
+    /// Apply hdiff patches for the given files relative to the installation folder
+    ///
+    /// Works the same way as `apply_hdiff_patches`. If `InstallOptions::continue_on_patch_error`
+    /// is set, then failed files are skipped and listed in the report
+    ///
+    /// Return report with only patched and failed files filled
+    pub fn apply_hdiff_patches_with_failures(&self, path: impl AsRef<Path>, files: Vec<String>, options: &InstallOptions, updater: impl Fn(DiffUpdate)) -> Result<InstallReport, DiffDownloadingError> {
+        let path = path.as_ref();
+
+        // Fail before making any changes instead of trying to repair every file
+        if !files.is_empty() && !options.patcher.is_available() {
+            tracing::error!("hdiff patcher is missing or can't be executed");
+
+            return Err(DiffDownloadingError::HpatchzMissing);
+        }
+
+        tracing::debug!("Applying hdiff patches");
+
+        (updater)(DiffUpdate::ApplyingHdiffStarted);
+
+        let hdiffs = files.len() as u64;
+
+        let mut patched_files = Vec::with_capacity(files.len());
+        let mut failed_patches = Vec::new();
+        let mut skipped_patches = Vec::new();
+
+        // Used to skip already patched files and to verify the patched ones
+        let integrity = crate::repairer::read_local_integrity_files(path);
+
+        let expected = integrity.as_ref()
+            .map(|integrity| integrity.iter()
+                .map(|file| (file.path.as_path(), file))
+                .collect::<HashMap<_, _>>())
+            .unwrap_or_default();
+
+        // Skip files which were already patched
+        let files = match options.skip_verified.then_some(&integrity) {
+            Some(Ok(integrity)) => {
+                let (files, verified) = manifest::split_verified_hdiff_files(path, files, integrity);
+
+                for file in verified {
+                    tracing::debug!("Skipping already patched file: {file}");
+
+                    let patch = path.join(format!("{file}.hdiff"));
+
+                    if patch.exists() {
+                        if let Err(err) = std::fs::remove_file(&patch) {
+                            return Err(DiffDownloadingError::FileRemoveFailed(patch, err.to_string()));
                         }
+                    }
+
+                    patched_files.push(path.join(file));
+
+                    (updater)(DiffUpdate::ApplyingHdiffProgress(patched_files.len() as u64, hdiffs));
+                }
+
+                files
+            }
+
+            Some(Err(err)) => {
+                tracing::warn!("Failed to read integrity files, not skipping verified patches: {err}");
+
+                files
+            }
+
+            None => files
+        };
+
+        let queue = Mutex::new(files.into_iter().collect::<VecDeque<String>>());
+        let failure = Mutex::new(None);
 
-                        Ok(None) => {
-                            tracing::error!("Failed to repair corrupted file: not found");
+        let (send, recv) = std::sync::mpsc::channel();
 
-                            return Err(Self::Error::HdiffPatch(err.to_string()))
+        tracing::debug!("Initiating {} hdiff workers", options.hdiff_workers);
+
+        std::thread::scope(|scope| {
+            for _ in 0..options.hdiff_workers.max(1) {
+                let send = send.clone();
+
+                let queue = &queue;
+                let failure = &failure;
+                let expected = &expected;
+
+                scope.spawn(move || {
+                    loop {
+                        // Stop the worker if some other one has failed
+                        if failure.lock().unwrap().is_some() {
+                            break;
                         }
 
-                        Err(repair_fail) => {
-                            tracing::error!("Failed to repair corrupted file: {repair_fail}");
+                        if options.is_cancelled() {
+                            failure.lock().unwrap().get_or_insert(DiffDownloadingError::Cancelled);
 
-                            return Err(Self::Error::HdiffPatch(err.to_string()))
+                            break;
                         }
+
+                        let task = queue.lock()
+                            .unwrap()
+                            .pop_front();
+
+                        let Some(file) = task else {
+                            break;
+                        };
+
+                        // Interrupted patches are finished because the original file is already removed
+                        let skip = manifest::hdiff_state(path, &file) == HdiffState::Pending &&
+                            options.patcher.decide(&path.join(&file)) == PatchDecision::Skip;
+
+                        let result = if skip {
+                            self.skip_hdiff_patch(path, &file)
+                        } else {
+                            let integrity = expected.get(Path::new(&file)).copied();
+
+                            self.apply_hdiff_patch(path, &file, integrity, options)
+                        };
+
+                        match result {
+                            Ok(()) if skip => {
+                                let _ = send.send(PatchResult::Skipped(path.join(file)));
+                            }
+
+                            Ok(()) => {
+                                let _ = send.send(PatchResult::Patched(path.join(file)));
+                            }
+
+                            Err(err) if options.continue_on_patch_error && err != DiffDownloadingError::Cancelled => {
+                                tracing::warn!("Failed to apply hdiff patch for {file}: {err}. Continuing installation");
+
+                                let _ = send.send(PatchResult::Failed(path.join(file), err.to_string()));
+                            }
+
+                            // Keep only the first error
+                            Err(err) => {
+                                failure.lock().unwrap().get_or_insert(err);
+
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+
+            drop(send);
+
+            while let Ok(result) = recv.recv() {
+                match result {
+                    PatchResult::Patched(file) => patched_files.push(file),
+                    PatchResult::Skipped(file) => skipped_patches.push(file),
+                    PatchResult::Failed(file, err) => failed_patches.push((file, err))
+                }
+
+                let processed = patched_files.len() + failed_patches.len() + skipped_patches.len();
+
+                (updater)(DiffUpdate::ApplyingHdiffProgress(processed as u64, hdiffs));
+            }
+        });
+
+        if let Some(err) = failure.into_inner().unwrap() {
+            if err == DiffDownloadingError::Cancelled {
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::Cancelled));
+            }
+
+            return Err(err);
+        }
+
+        (updater)(DiffUpdate::ApplyingHdiffFinished);
+
+        Ok(InstallReport {
+            patched_files,
+            failed_patches,
+            skipped_patches,
+            ..InstallReport::default()
+        })
+    }
+
+    #[cfg(feature = "install")]
+    /// Keep the file unchanged removing its hdiff patch and partially patched file if they exist
+    fn skip_hdiff_patch(&self, path: &Path, relative_file: &str) -> Result<(), DiffDownloadingError> {
+        if !manifest::is_safe_path(path, relative_file) {
+            tracing::error!("Hdiff patch leads outside of the installation folder: {relative_file}");
+
+            return Err(DiffDownloadingError::UnsafePath(PathBuf::from(relative_file)));
+        }
+
+        tracing::debug!("Skipping hdiff patch for {relative_file}");
+
+        for artifact in [format!("{relative_file}.hdiff"), format!("{relative_file}.hdiff_patched")] {
+            let artifact = path.join(artifact);
+
+            if artifact.exists() {
+                if let Err(err) = std::fs::remove_file(&artifact) {
+                    return Err(DiffDownloadingError::FileRemoveFailed(artifact, err.to_string()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "install")]
+    /// Install the difference into the path returned by the `installation_path` method using specified options
+    ///
+    /// Return `DiffDownloadingError::PathNotSpecified` if the installation path is not provided
+    pub fn install_with(&self, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError> {
+        let Some(path) = self.installation_path() else {
+            return Err(DiffDownloadingError::PathNotSpecified);
+        };
+
+        self.install_to_by(path, options, updater)
+    }
+
+    #[cfg(feature = "install")]
+    /// Install the difference the same way as `install_to_by`,
+    /// reporting overall progress in `[0.0, 1.0]` range
+    ///
+    /// See `DiffUpdate::overall_progress`
+    pub fn install_to_with_progress(&self, path: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(f32) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError> {
+        let updater = progress.clone();
+
+        let report = self.install_to_by(path, options, move |update| {
+            if let Some(fraction) = update.overall_progress() {
+                (updater)(fraction);
+            }
+        })?;
+
+        // Some phases could be skipped
+        (progress)(1.0);
+
+        Ok(report)
+    }
+
+    #[cfg(feature = "install")]
+    /// Try to download the diff into the specified folder using given options,
+    /// using `VersionDiffExt::file_name` result as a name of the file to be saved as
+    pub fn download_to_by(&mut self, folder: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) + Send + 'static) -> Result<(), DiffDownloadingError> {
+        let filename = self.file_name()
+            .expect("Failed to resolve downloading file name");
+
+        self.download_as_by(folder.as_ref().join(filename), options, progress)
+    }
+
+    #[cfg(feature = "install")]
+    /// Try to download the diff into the specified folder using given options
+    /// to install it later using the `install_downloaded` method
+    ///
+    /// Return handle of the downloaded archive which can be stored between launches
+    pub fn download_archive_to(&mut self, folder: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) + Send + 'static) -> Result<DownloadedArchive, DiffDownloadingError> {
+        let filename = self.file_name()
+            .expect("Failed to resolve downloading file name");
+
+        let path = folder.as_ref().join(filename);
+
+        self.download_as_by(&path, options, progress)?;
+
+        let checksum = match self {
+            Self::Predownload { checksum, .. } |
+            Self::Diff { checksum, .. } => checksum.clone(),
+
+            _ => None
+        };
+
+        Ok(DownloadedArchive {
+            path,
+            checksum,
+            version: self.latest()
+        })
+    }
+
+    #[cfg(feature = "install")]
+    #[inline]
+    /// Try to download the diff into the specified path using given options
+    pub fn download_as_by(&mut self, path: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) + Send + 'static) -> Result<(), DiffDownloadingError> {
+        self.download_as_with_control(path, options, move |current, total| {
+            progress(current, total);
+
+            ControlFlow::Continue(())
+        })
+    }
+
+    #[cfg(feature = "install")]
+    /// Try to download the diff into the specified folder using given options,
+    /// stopping downloading when the progress callback returns `ControlFlow::Break`
+    ///
+    /// Return `DiffDownloadingError::Cancelled` if downloading was stopped by the callback
+    pub fn download_to_with_control(&mut self, folder: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) -> ControlFlow<()> + Send + 'static) -> Result<(), DiffDownloadingError> {
+        let filename = self.file_name()
+            .expect("Failed to resolve downloading file name");
+
+        self.download_as_with_control(folder.as_ref().join(filename), options, progress)
+    }
+
+    #[cfg(feature = "install")]
+    /// Try to download the diff into the specified path using given options,
+    /// stopping downloading when the progress callback returns `ControlFlow::Break`
+    ///
+    /// Return `DiffDownloadingError::Cancelled` if downloading was stopped by the callback
+    pub fn download_as_with_control(&mut self, path: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) -> ControlFlow<()> + Send + 'static) -> Result<(), DiffDownloadingError> {
+        tracing::debug!("Downloading version difference");
+
+        let mut downloader = options.downloader(match self {
+            // Can't be downloaded
+            Self::Latest { .. } => return Err(DiffDownloadingError::AlreadyLatest),
+            Self::Outdated { .. } => return Err(DiffDownloadingError::Outdated),
+
+            // Can be downloaded
+            Self::Predownload { uri, .. } |
+            Self::Diff { uri, .. } => uri,
+
+            // Can be installed but amogus
+            Self::NotInstalled { .. } => return Err(DiffDownloadingError::MultipleSegments)
+        })?;
+
+        if let Some(downloaded_size) = self.downloaded_size() {
+            downloader = downloader.with_expected_size(downloaded_size);
+        }
+
+        match downloader.download_with_control(path.as_ref(), progress) {
+            Ok(()) => (),
+
+            Err(DownloadingError::Cancelled) => return Err(DiffDownloadingError::Cancelled),
+
+            Err(err) => {
+                tracing::error!("Failed to download version difference: {err}");
+
+                return Err(err.into());
+            }
+        }
+
+        if let Self::Predownload { checksum, .. } | Self::Diff { checksum, .. } = self {
+            verify_checksum(path.as_ref(), checksum.as_deref())?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    /// Asynchronous variant of the `install_to_by` method
+    ///
+    /// Installation is performed by the `install_to_by` method in a blocking tokio task,
+    /// and its progress updates are sent to the `updater` channel. Downloading is not
+    /// asynchronous: it still uses blocking requests, but they don't block the async runtime
+    ///
+    /// Use `InstallOptions::cancel_flag` to stop the installation. Dropping the returned
+    /// future sets this flag as well, so the blocking task stops as soon as possible
+    /// instead of continuing the installation in background
+    pub async fn install_to_async(self, path: impl Into<PathBuf>, mut options: InstallOptions, updater: tokio::sync::mpsc::UnboundedSender<DiffUpdate>) -> Result<InstallReport, DiffDownloadingError> {
+        /// Set the cancel flag if the future is dropped before the task is finished
+        struct CancelOnDrop(Option<Arc<AtomicBool>>);
+
+        impl Drop for CancelOnDrop {
+            fn drop(&mut self) {
+                if let Some(flag) = self.0.take() {
+                    flag.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let path = path.into();
+
+        let cancel_flag = options.cancel_flag.get_or_insert_with(Default::default).clone();
+
+        let mut guard = CancelOnDrop(Some(cancel_flag));
+
+        let task = tokio::task::spawn_blocking(move || {
+            self.install_to_by(path, &options, move |update| {
+                // Ignore errors because receiver may be dropped if the caller isn't interested in updates
+                let _ = updater.send(update);
+            })
+        });
+
+        let result = task.await;
+
+        // Task is finished, so there's nothing to cancel
+        guard.0 = None;
+
+        match result {
+            Ok(result) => result,
+
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(_) => Err(DiffDownloadingError::Cancelled)
+        }
+    }
+
+    #[cfg(feature = "install")]
+    #[inline]
+    /// Verify files installed into the path returned by `installation_path` method
+    ///
+    /// Return list of missing or corrupted files relative to the installation path
+    pub fn verify(&self, progress: impl Fn(u64, u64)) -> Result<Vec<PathBuf>, DiffDownloadingError> {
+        let Some(path) = self.installation_path() else {
+            return Err(DiffDownloadingError::PathNotSpecified);
+        };
+
+        self.verify_in(path, progress)
+    }
+
+    #[cfg(feature = "install")]
+    /// Verify files installed into the given location using `pkg_version` manifests stored there
+    ///
+    /// Return list of missing or corrupted files relative to the installation path
+    pub fn verify_in(&self, path: impl AsRef<Path>, progress: impl Fn(u64, u64)) -> Result<Vec<PathBuf>, DiffDownloadingError> {
+        let path = path.as_ref();
+
+        tracing::debug!("Verifying installed files");
+
+        let files = match crate::repairer::read_local_integrity_files(path) {
+            Ok(files) => files,
+            Err(err) => return Err(DiffDownloadingError::ManifestReadFailed(path.join("pkg_version"), err.to_string()))
+        };
+
+        Ok(crate::repairer::verify_files(path, &files, progress))
+    }
+
+    #[cfg(feature = "install")]
+    /// Extract single-segment archive while it's being downloaded
+    ///
+    /// Return `false` if the archive can't be streamed and should be downloaded first
+    fn install_streamed(&self, uri: &str, path: &Path, options: &InstallOptions, updater: &(impl Fn(DiffUpdate) + Clone + Send + 'static), report: &mut InstallReport) -> Result<bool, DiffDownloadingError> {
+        tracing::debug!("Extracting archive while downloading it");
+
+        let downloader = options.downloader(uri)?;
+        let progress_updater = updater.clone();
+
+        (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingStarted(path.to_path_buf())));
+
+        let mut stream = downloader.stream(move |current, total| {
+            (progress_updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(current, total)));
+        })?;
+
+        (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingStarted(path.to_path_buf())));
+
+        match archives::extract_stream(&mut stream, downloader.get_filename(), path) {
+            Ok(Some(unpacked)) => {
+                report.unpacked_bytes = unpacked;
+                report.downloads.extend(downloader.download_info());
+
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingFinished));
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingProgress(unpacked, unpacked)));
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingFinished));
+            }
+
+            Ok(None) => {
+                tracing::warn!("Archive can't be extracted while downloading. Downloading it first");
+
+                return Ok(false);
+            }
+
+            Err(_) if options.is_cancelled() => {
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::Cancelled));
+
+                return Err(DiffDownloadingError::Cancelled);
+            }
+
+            // Installation can't be continued over partially extracted files
+            Err(err) => {
+                tracing::error!("Failed to extract archive while downloading it: {err}");
+
+                (updater)(DiffUpdate::InstallerUpdate(InstallerUpdate::UnpackingError(err.to_string())));
+
+                return Err(DiffDownloadingError::Unpacking(PathBuf::from(downloader.get_filename()), err.to_string()));
+            }
+        }
+
+        Ok(true)
+    }
+
+    #[cfg(feature = "install")]
+    /// Apply hdiff patch for a single file
+    ///
+    /// Patched file is verified using the `integrity` entry if it's given, and then
+    /// moved over the original one, so the original file is never removed before
+    /// its replacement is ready. If the patch can't be applied then the file will be re-downloaded
+    fn apply_hdiff_patch(&self, path: &Path, relative_file: &str, integrity: Option<&IntegrityFile>, options: &InstallOptions) -> Result<(), DiffDownloadingError> {
+        if !manifest::is_safe_path(path, relative_file) {
+            tracing::error!("Hdiff patch leads outside of the installation folder: {relative_file}");
+
+            return Err(DiffDownloadingError::UnsafePath(PathBuf::from(relative_file)));
+        }
+
+        let file = path.join(relative_file);
+        let patch = path.join(format!("{relative_file}.hdiff"));
+        let output = path.join(format!("{relative_file}.hdiff_patched"));
+
+        // Previous installation attempt could be interrupted after processing this file
+        match manifest::hdiff_state(path, relative_file) {
+            HdiffState::Pending => (),
+
+            HdiffState::Applied => {
+                tracing::debug!("Hdiff patch is already applied for {:?}", file);
+
+                return Ok(());
+            }
+
+            HdiffState::Interrupted => {
+                tracing::debug!("Finishing interrupted hdiff patch for {:?}", file);
+
+                if let Err(err) = std::fs::rename(&output, &file) {
+                    return Err(DiffDownloadingError::FileRenameFailed(output, err.to_string()));
+                }
+
+                if patch.exists() {
+                    if let Err(err) = std::fs::remove_file(&patch) {
+                        return Err(DiffDownloadingError::FileRemoveFailed(patch, err.to_string()));
                     }
+                }
+
+                return Ok(());
+            }
+        }
+
+        // Patched file could remain from the interrupted installation
+        if output.exists() {
+            if let Err(err) = std::fs::remove_file(&output) {
+                return Err(DiffDownloadingError::FileRemoveFailed(output, err.to_string()));
+            }
+        }
+
+        let patched = options.patcher.patch(&file, &patch, &output)
+            .and_then(|_| verify_patched_file(&file, &output, integrity));
+
+        // If failed to apply the patch
+        if let Err(err) = patched {
+            // Original file is still in place so we can remove the patched one
+            #[allow(unused_must_use)] {
+                std::fs::remove_file(&output);
+            }
 
-                    #[allow(unused_must_use)] {
-                        std::fs::remove_file(&patch);
+            tracing::warn!("Failed to apply hdiff patch for {:?}: {err}", file);
+            tracing::debug!("Trying to repair corrupted file");
+
+            // If we were able to get API response - it shouldn't be impossible
+            // to also get integrity files list from the same API
+            match super::repairer::try_get_integrity_file(self.edition(), relative_file, Some(*crate::REQUESTS_TIMEOUT)) {
+                Ok(Some(integrity)) => {
+                    if !integrity.fast_verify(path) {
+                        if let Err(err) = integrity.repair(path) {
+                            tracing::error!("Failed to repair corrupted file: {err}");
+
+                            return Err(err.into());
+                        }
                     }
                 }
 
-                // If patch was successfully applied
-                else {
-                    // FIXME: handle errors properly
-                    std::fs::remove_file(&file)
-                        .expect(&format!("Failed to remove hdiff patch: {:?}", file));
+                Ok(None) => {
+                    tracing::error!("Failed to repair corrupted file: not found");
+
+                    return Err(DiffDownloadingError::HdiffPatch(err))
+                }
 
-                    std::fs::remove_file(&patch)
-                        .expect(&format!("Failed to remove hdiff patch: {:?}", patch));
+                Err(repair_fail) => {
+                    tracing::error!("Failed to repair corrupted file: {repair_fail}");
 
-                    std::fs::rename(&output, &file)
-                        .expect(&format!("Failed to rename hdiff patch: {:?}", file));
+                    return Err(DiffDownloadingError::HdiffPatch(err))
                 }
+            }
 
-                (updater)(Self::Update::ApplyingHdiffProgress(i as u64 + 1, hdiffs));
+            #[allow(unused_must_use)] {
+                std::fs::remove_file(&patch);
             }
+        }
 
-            std::fs::remove_file(path.join("hdifffiles.txt"))
-                .expect("Failed to remove hdifffiles.txt");
+        // If patch was successfully applied
+        else {
+            // Patched file gets modification time of the patch extracted from the archive
+            if options.preserve_mtime {
+                let mtime = patch.metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .and_then(|mtime| std::fs::File::options().write(true).open(&output)?.set_modified(mtime));
 
-            (updater)(Self::Update::ApplyingHdiffFinished);
+                if let Err(err) = mtime {
+                    tracing::warn!("Failed to set modification time of the patched file {:?}: {err}", file);
+                }
+            }
+
+            // Rename replaces the original file atomically
+            if let Err(err) = std::fs::rename(&output, &file) {
+                #[allow(unused_must_use)] {
+                    std::fs::remove_file(&output);
+                }
+
+                return Err(DiffDownloadingError::FileRenameFailed(output, err.to_string()));
+            }
+
+            if let Err(err) = std::fs::remove_file(&patch) {
+                return Err(DiffDownloadingError::FileRemoveFailed(patch, err.to_string()));
+            }
         }
 
-        tracing::debug!("Deleting outdated files");
+        Ok(())
+    }
+}
 
-        // Remove outdated files
-        // We're ignoring Err because in practice it means that deletefiles.txt is missing
-        if let Ok(files) = std::fs::read_to_string(path.join("deletefiles.txt")) {
-            let files = files.lines().collect::<Vec<&str>>();
-            let files_len = files.len() as u64;
+impl VersionDiffExt for VersionDiff {
+    type Error = DiffDownloadingError;
+    type Update = DiffUpdate;
+    type Edition = GameEdition;
 
-            (updater)(Self::Update::RemovingOutdatedStarted);
+    fn edition(&self) -> GameEdition {
+        match self {
+            Self::Latest { edition, .. } |
+            Self::Predownload { edition, .. } |
+            Self::Diff { edition, .. } |
+            Self::Outdated { edition, .. } |
+            Self::NotInstalled { edition, .. } => *edition
+        }
+    }
 
-            // AnimeGame_Data/Plugins/metakeeper.dll
-            for (i, file) in files.into_iter().enumerate() {
-                let file = path.join(file);
+    fn current(&self) -> Option<Version> {
+        match self {
+            Self::Latest { version: current, .. } |
+            Self::Predownload { current, .. } |
+            Self::Diff { current, .. } |
+            Self::Outdated { current, .. } => Some(*current),
 
-                std::fs::remove_file(&file)
-                    .expect(&format!("Failed to remove outdated file: {:?}", file));
+            Self::NotInstalled { .. } => None
+        }
+    }
 
-                (updater)(Self::Update::RemovingOutdatedProgress(i as u64 + 1, files_len));
+    fn latest(&self) -> Version {
+        match self {
+            Self::Latest { version: latest, .. } |
+            Self::Predownload { latest, .. } |
+            Self::Diff { latest, .. } |
+            Self::Outdated { latest, .. } |
+            Self::NotInstalled { latest, .. } => *latest
+        }
+    }
+
+    fn downloaded_size(&self) -> Option<u64> {
+        match self {
+            // Can't be installed
+            Self::Latest { .. } |
+            Self::Outdated { .. } => None,
+
+            // Can be installed
+            Self::Predownload { downloaded_size, .. } |
+            Self::Diff { downloaded_size, .. } |
+            Self::NotInstalled { downloaded_size, .. } => Some(*downloaded_size)
+        }
+    }
+
+    fn unpacked_size(&self) -> Option<u64> {
+        match self {
+            // Can't be installed
+            Self::Latest { .. } |
+            Self::Outdated { .. } => None,
+
+            // Can be installed
+            Self::Predownload { unpacked_size, .. } |
+            Self::Diff { unpacked_size, .. } |
+            Self::NotInstalled { unpacked_size, .. } => Some(*unpacked_size)
+        }
+    }
+
+    fn installation_path(&self) -> Option<&Path> {
+        match self {
+            // Can't be installed
+            Self::Latest { .. } |
+            Self::Outdated { .. } => None,
+
+            // Can be installed
+            Self::Predownload { installation_path, .. } |
+            Self::Diff { installation_path, .. } |
+            Self::NotInstalled { installation_path, .. } => match installation_path {
+                Some(path) => Some(path.as_path()),
+                None => None
             }
+        }
+    }
 
-            std::fs::remove_file(path.join("deletefiles.txt"))
-                .expect("Failed to remove deletefiles.txt");
+    fn downloading_uri(&self) -> Option<String> {
+        match self {
+            // Can't be installed
+            Self::Latest { .. } |
+            Self::Outdated { .. } => None,
 
-            (updater)(Self::Update::RemovingOutdatedFinished);
+            // Can be installed
+            Self::Predownload { uri, .. } |
+            Self::Diff { uri, .. } => Some(uri.to_owned()),
+
+            // Can be installed but amogus
+            Self::NotInstalled { .. } => None
         }
+    }
+
+    #[inline]
+    fn download_as(&mut self, path: impl AsRef<Path>, progress: impl Fn(u64, u64) + Send + 'static) -> Result<(), Self::Error> {
+        self.download_as_by(path, &InstallOptions::default(), progress)
+    }
+
+    #[inline]
+    fn install_to(&self, path: impl AsRef<Path>, updater: impl Fn(Self::Update) + Clone + Send + 'static) -> Result<(), Self::Error> {
+        self.install_to_by(path, &InstallOptions::default(), updater)?;
 
         Ok(())
     }
 }
+
+#[cfg(feature = "install")]
+impl InstallableDiff for VersionDiff {
+    #[inline]
+    fn temp_folder(&self) -> PathBuf {
+        VersionDiff::temp_folder(self)
+    }
+
+    #[inline]
+    fn version_file_path(&self) -> Option<PathBuf> {
+        VersionDiff::version_file_path(self)
+    }
+
+    #[inline]
+    fn download_archive_to(&mut self, folder: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) + Send + 'static) -> Result<DownloadedArchive, DiffDownloadingError> {
+        VersionDiff::download_archive_to(self, folder, options, progress)
+    }
+
+    #[inline]
+    fn install_downloaded(&self, archive: &DownloadedArchive, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError> {
+        VersionDiff::install_downloaded(self, archive, path, options, updater)
+    }
+
+    #[inline]
+    fn install_to_by(&self, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError> {
+        VersionDiff::install_to_by(self, path, options, updater)
+    }
+}
+
+/// Ordered list of the game version differences which should be installed one after another
+pub type DiffChain = crate::installer::diff::DiffChain<VersionDiff>;
+
+/// Find chain of the game version differences from the `start` version to the `target` one
+///
+/// Every patch listed by the API updates the game to the version of its `major` block, so
+/// chains can be formed using both the main and predownload packages. On every step the patch
+/// updating the game to the newest version not exceeding the `target` is chosen
+///
+/// Return `DiffDownloadingError::Outdated` if the chain can't be formed
+pub fn diff_chain(start: Version, target: Version, package: &GamePackage, edition: GameEdition, installation_path: Option<PathBuf>) -> Result<DiffChain, DiffDownloadingError> {
+    let releases = std::iter::once((&package.main.major, &package.main.patches))
+        .chain(package.pre_download.iter().flat_map(|info| info.major.as_ref().map(|major| (major, &info.patches))));
+
+    // (from, to, patch segments)
+    let mut steps = Vec::new();
+
+    for (major, patches) in releases {
+        let Some(latest) = Version::from_str(&major.version) else {
+            continue;
+        };
+
+        for patch in patches {
+            if let Some(current) = Version::from_str(&patch.version) {
+                if !patch.game_pkgs.is_empty() {
+                    steps.push((current, latest, &patch.game_pkgs));
+                }
+            }
+        }
+    }
+
+    crate::installer::diff::find_chain(start, target, &steps, |current, latest, segments| {
+        let downloaded_size = segments.iter()
+            .flat_map(|segment| segment.size.parse::<u64>())
+            .sum();
+
+        let unpacked_size = segments.iter()
+            .flat_map(|segment| segment.decompressed_size.parse::<u64>())
+            .sum::<u64>()
+            .saturating_sub(downloaded_size);
+
+        VersionDiff::Diff {
+            current,
+            latest,
+
+            uri: segments[0].url.clone(),
+            checksum: Some(segments[0].md5.clone()),
+            edition,
+            kind: DiffKind::Game,
+
+            downloaded_size,
+            unpacked_size,
+
+            installation_path: installation_path.clone(),
+            version_file_path: None,
+            temp_folder: None
+        }
+    })
+}
+
@@ -358,6 +358,7 @@ impl VoicePackage {
                                     current,
                                     latest: Version::from_str(predownload_major.version).unwrap(),
                                     uri: diff.url,
+                                    checksum: Some(diff.md5),
 
                                     downloaded_size: diff.size.parse::<u64>().unwrap(),
                                     unpacked_size: diff.decompressed_size.parse::<u64>().unwrap(),
@@ -378,7 +379,8 @@ impl VoicePackage {
                                     },
 
                                     temp_folder: None,
-                                    edition: game_edition
+                                    edition: game_edition,
+                                    kind: DiffKind::VoicePackage(self.locale())
                                 })
                             }
                         }
@@ -387,7 +389,8 @@ impl VoicePackage {
 
                 Ok(VersionDiff::Latest {
                     version: current,
-                    edition: game_edition
+                    edition: game_edition,
+                    kind: DiffKind::VoicePackage(self.locale())
                 })
             }
 
@@ -402,6 +405,7 @@ impl VoicePackage {
                             current,
                             latest: Version::from_str(response.main.major.version).unwrap(),
                             uri: diff.url,
+                            checksum: Some(diff.md5),
 
                             downloaded_size: diff.size.parse::<u64>().unwrap(),
                             unpacked_size: diff.decompressed_size.parse::<u64>().unwrap(),
@@ -422,7 +426,8 @@ impl VoicePackage {
                             },
 
                             temp_folder: None,
-                            edition: game_edition
+                            edition: game_edition,
+                            kind: DiffKind::VoicePackage(self.locale())
                         })
                     }
                 }
@@ -430,7 +435,8 @@ impl VoicePackage {
                 Ok(VersionDiff::Outdated {
                     current,
                     latest: Version::from_str(response.main.major.version).unwrap(),
-                    edition: game_edition
+                    edition: game_edition,
+                    kind: DiffKind::VoicePackage(self.locale())
                 })
             }
         }
@@ -443,6 +449,7 @@ impl VoicePackage {
             Ok(VersionDiff::NotInstalled {
                 latest: Version::from_str(response.main.major.version).unwrap(),
                 segments_uris: vec![latest.url],
+                segments_checksums: Some(vec![latest.md5]),
 
                 downloaded_size: latest.size.parse::<u64>().unwrap(),
                 unpacked_size: latest.decompressed_size.parse::<u64>().unwrap(),
@@ -463,7 +470,8 @@ impl VoicePackage {
                 },
 
                 temp_folder: None,
-                edition: game_edition
+                edition: game_edition,
+                kind: DiffKind::VoicePackage(self.locale())
             })
         }
     }
@@ -55,6 +55,17 @@ pub fn try_get_integrity_file(game_edition: GameEdition, relative_path: impl AsR
     Ok(None)
 }
 
+/// Verify game files using the latest `pkg_version` manifest and re-download missing or corrupted ones
+///
+/// Return list of repaired files' paths relative to the `game_dir`
+///
+/// `progress` is called with `(processed files, total files)` values
+pub fn repair(game_edition: GameEdition, game_dir: impl AsRef<Path>, progress: impl Fn(u64, u64)) -> anyhow::Result<Vec<PathBuf>> {
+    let files = try_get_integrity_files(game_edition, None)?;
+
+    Ok(crate::repairer::repair_files(game_dir, &files, progress)?)
+}
+
 /// Try to get list of files that are not more used by the game and can be deleted
 /// 
 /// ⚠️ Be aware that the game can create its own files after downloading, so "unused files" may not be really unused.
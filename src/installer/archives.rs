@@ -1,8 +1,14 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs::File;
+use std::ffi::OsString;
+use std::io::{Read, Write, Seek, BufRead, BufReader};
+use std::collections::HashMap;
 use std::process::{Command, Stdio};
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use serde::{Serialize, Deserialize};
+use thiserror::Error;
 
 use zip::ZipArchive;
 use tar::Archive as TarArchive;
@@ -11,6 +17,7 @@ use tar::Archive as TarArchive;
 use xz::read::XzDecoder as XzReader;
 use bzip2::read::BzDecoder as Bz2Reader;
 use flate2::read::GzDecoder as GzReader;
+use zstd::stream::read::Decoder as ZstdReader;
 
 /// Get 7z binary if some is available
 fn get7z() -> anyhow::Result<String> {
@@ -59,54 +66,208 @@ pub struct Entry {
     pub size: Size
 }
 
+/// How files which already exist in the output folder are handled during extraction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OverwritePolicy {
+    /// Replace existing files by the archive entries
+    #[default]
+    AlwaysOverwrite,
+
+    /// Keep existing files
+    SkipIfExists,
+
+    /// Replace existing files only if the archive entry was modified later
+    ///
+    /// Entries without stored modification time, e.g. from 7z archives, are always extracted
+    OverwriteIfNewer
+}
+
+impl OverwritePolicy {
+    /// Check if the archive entry should be extracted to the `path`
+    pub fn should_extract(&self, path: impl AsRef<Path>, entry_mtime: Option<SystemTime>) -> bool {
+        let Ok(metadata) = path.as_ref().symlink_metadata() else {
+            return true;
+        };
+
+        if metadata.is_dir() {
+            return true;
+        }
+
+        match self {
+            Self::AlwaysOverwrite => true,
+            Self::SkipIfExists => false,
+
+            Self::OverwriteIfNewer => match (entry_mtime, metadata.modified()) {
+                (Some(entry_mtime), Ok(mtime)) => entry_mtime > mtime,
+
+                _ => true
+            }
+        }
+    }
+}
+
+/// Options of the `Archive::extract_by` method
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ExtractOptions {
+    /// How already existing files are handled
+    pub overwrite_policy: OverwritePolicy,
+
+    /// Decompress 7z archives using a single thread
+    ///
+    /// Multi-threaded decompression allocates buffers for every thread, so a single one
+    /// lowers memory usage at the cost of slower extraction on multi-core CPUs. Dictionary
+    /// size is chosen when the archive is created and can't be lowered. Zip and tar archives
+    /// are always extracted entry by entry with small buffers, so they're not affected
+    pub low_memory: bool,
+
+    /// Don't write archive entries identical to the already existing files
+    ///
+    /// Zip and 7z entries are compared using their sizes and CRC32 checksums stored
+    /// in the archive, so existing files have to be hashed. Tar entries are compared
+    /// with the files while they're decompressed, and only changed files are written
+    pub skip_identical: bool
+}
+
+impl ExtractOptions {
+    #[inline]
+    /// Specify how already existing files should be handled
+    pub fn with_overwrite_policy(mut self, overwrite_policy: OverwritePolicy) -> Self {
+        self.overwrite_policy = overwrite_policy;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether 7z archives should be decompressed using a single thread
+    pub fn with_low_memory(mut self, low_memory: bool) -> Self {
+        self.low_memory = low_memory;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether entries identical to the existing files should be skipped
+    pub fn with_skip_identical(mut self, skip_identical: bool) -> Self {
+        self.skip_identical = skip_identical;
+
+        self
+    }
+}
+
+/// Archive format is not supported or can't be detected
+///
+/// `(path)`
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[error("Archive format is not supported: {0:?}")]
+pub struct UnsupportedArchive(pub PathBuf);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarXz,
+    TarGz,
+    TarBz2,
+    TarZst,
+    SevenZ
+}
+
+impl ArchiveFormat {
+    /// Amount of bytes needed to detect archive format
+    pub const HEADER_SIZE: usize = 262;
+
+    /// Detect archive format from its first bytes
+    ///
+    /// Compressed streams (xz, gz, bz2, zstd) are expected to contain tar archives
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        }
+
+        else if header.starts_with(b"7z\xBC\xAF\x27\x1C") {
+            Some(Self::SevenZ)
+        }
+
+        else if header.starts_with(b"\xFD7zXZ\x00") {
+            Some(Self::TarXz)
+        }
+
+        else if header.starts_with(b"\x1F\x8B") {
+            Some(Self::TarGz)
+        }
+
+        else if header.starts_with(b"BZh") {
+            Some(Self::TarBz2)
+        }
+
+        else if header.starts_with(b"\x28\xB5\x2F\xFD") {
+            Some(Self::TarZst)
+        }
+
+        else if header.get(257..262) == Some(b"ustar") {
+            Some(Self::Tar)
+        }
+
+        else {
+            None
+        }
+    }
+
+    /// Detect format of the archive stored on the disk
+    pub fn detect(path: impl AsRef<Path>) -> std::io::Result<Option<Self>> {
+        let mut header = Vec::with_capacity(Self::HEADER_SIZE);
+
+        File::open(path)?
+            .take(Self::HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+
+        Ok(Self::from_magic(&header))
+    }
+}
+
 pub enum Archive {
     Zip(PathBuf, ZipArchive<File>),
     Tar(PathBuf, TarArchive<File>),
     TarXz(PathBuf, TarArchive<XzReader<File>>),
     TarGz(PathBuf, TarArchive<GzReader<File>>),
     TarBz2(PathBuf, TarArchive<Bz2Reader<File>>),
+    TarZst(PathBuf, TarArchive<ZstdReader<'static, BufReader<File>>>),
     SevenZ(PathBuf/*, SevenzArchive<File>*/),
     ZipMultipart(PathBuf)
 }
 
 impl Archive {
+    /// Open archive, detecting its format from the file content
+    ///
+    /// Multipart archives are detected by their extension.
+    /// Returns `UnsupportedArchive` error if the format is unknown
     pub fn open<T: Into<PathBuf>>(path: T) -> anyhow::Result<Self> {
         let path: PathBuf = path.into();
-        let file = File::open(&path)?;
 
         let path_str = path.to_string_lossy();
 
-        if &path_str[path_str.len() - 4..] == ".zip" {
-            Ok(Archive::Zip(path, ZipArchive::new(file)?))
+        // First part of multipart archive has the same header as the regular one
+        if path_str.ends_with(".zip.001") || path_str.ends_with(".7z.001") || path_str.ends_with(".z01") {
+            return Ok(Archive::ZipMultipart(path));
         }
 
-        else if &path_str[path_str.len() - 7..] == ".tar.xz" {
-            Ok(Archive::TarXz(path, TarArchive::new(XzReader::new(file))))
-        }
-
-        else if &path_str[path_str.len() - 7..] == ".tar.gz" {
-            Ok(Archive::TarGz(path, TarArchive::new(GzReader::new(file))))
-        }
+        let Some(format) = ArchiveFormat::detect(&path)? else {
+            return Err(UnsupportedArchive(path).into());
+        };
 
-        else if &path_str[path_str.len() - 8..] == ".tar.bz2" {
-            Ok(Archive::TarBz2(path, TarArchive::new(Bz2Reader::new(file))))
-        }
-
-        else if &path_str[path_str.len() - 3..] == ".7z" {
-            Ok(Archive::SevenZ(path/*, SevenzArchive::open(path, &[])?*/))
-        }
+        let file = File::open(&path)?;
 
-        else if &path_str[path_str.len() - 4..] == ".tar" {
-            Ok(Archive::Tar(path, TarArchive::new(file)))
-        }
+        let archive = match format {
+            ArchiveFormat::Zip    => Archive::Zip(path, ZipArchive::new(file)?),
+            ArchiveFormat::Tar    => Archive::Tar(path, TarArchive::new(file)),
+            ArchiveFormat::TarXz  => Archive::TarXz(path, TarArchive::new(XzReader::new(file))),
+            ArchiveFormat::TarGz  => Archive::TarGz(path, TarArchive::new(GzReader::new(file))),
+            ArchiveFormat::TarBz2 => Archive::TarBz2(path, TarArchive::new(Bz2Reader::new(file))),
+            ArchiveFormat::TarZst => Archive::TarZst(path, TarArchive::new(ZstdReader::new(file)?)),
+            ArchiveFormat::SevenZ => Archive::SevenZ(path/*, SevenzArchive::open(path, &[])?*/)
+        };
 
-        else if &path_str[path_str.len() - 8..] == ".zip.001" || &path_str[path_str.len() - 7..] == ".7z.001" || &path_str[path_str.len() - 4..] == ".z01" {
-            Ok(Archive::ZipMultipart(path))
-        }
-
-        else {
-            Err(anyhow::anyhow!("Archive format is not supported: {}", path.to_string_lossy()))
-        }
+        Ok(archive)
     }
 
     /// Tar archives may forbid you to extract them if you call this method
@@ -164,6 +325,15 @@ impl Archive {
                 }
             }
 
+            Archive::TarZst(_, tar) => {
+                for entry in tar.entries()?.flatten() {
+                    entries.push(Entry {
+                        name: entry.path()?.to_str().unwrap().to_string(),
+                        size: Size::Compressed(entry.size())
+                    });
+                }
+            }
+
             #[allow(unused_must_use)]
             Archive::SevenZ(path) |
             Archive::ZipMultipart(path) => {
@@ -195,6 +365,11 @@ impl Archive {
                 let output = String::from_utf8(output.stdout)?;
 
                 let output = output.split("-------------------").collect::<Vec<&str>>();
+
+                // 7z doesn't print files list if the archive is corrupted
+                if output.len() < 3 {
+                    anyhow::bail!("Failed to list archive entries: {:?}", path);
+                }
                 let mut output = output[1..output.len() - 1].join("-------------------");
 
                 // In some cases 7z can report two ending sequences instead of one:
@@ -271,6 +446,10 @@ impl Archive {
                 tar.unpack(folder)?;
             }
 
+            Archive::TarZst(_, tar) => {
+                tar.unpack(folder)?;
+            }
+
             Archive::SevenZ(archive) |
             Archive::ZipMultipart(archive) => {
                 // sevenz_rust::decompress_file(archive, folder.into())?;
@@ -283,11 +462,16 @@ impl Archive {
                     .arg(&folder)
                     .output()?;
 
+                // Keep non-UTF-8 paths as is
+                let mut output = OsString::from("-o");
+
+                output.push(folder.as_os_str());
+
                 // Extract the archive
                 Command::new(get7z()?)
                     .arg("x")
                     .arg(archive)
-                    .arg(format!("-o{}", folder.to_string_lossy()))
+                    .arg(output)
                     .arg("-aoa")
                     .output()?;
 
@@ -302,4 +486,679 @@ impl Archive {
 
         Ok(())
     }
+
+    /// Extract only archive entries accepted by the `filter`
+    ///
+    /// `filter` is called with entries' names as they're stored in the archive
+    #[tracing::instrument(level = "debug", skip(self, filter))]
+    pub fn extract_filtered<T: Into<PathBuf> + std::fmt::Debug>(&mut self, folder: T, filter: impl Fn(&str) -> bool) -> anyhow::Result<()> {
+        self.extract_entries(folder.into(), |name, _| filter(name), &ExtractOptions::default())
+    }
+
+    /// Extract archive handling already existing files according to the `policy`
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn extract_with_policy<T: Into<PathBuf> + std::fmt::Debug>(&mut self, folder: T, policy: OverwritePolicy) -> anyhow::Result<()> {
+        if policy == OverwritePolicy::AlwaysOverwrite {
+            return self.extract(folder);
+        }
+
+        self.extract_by(folder, |_| true, &ExtractOptions::default().with_overwrite_policy(policy))
+    }
+
+    /// Extract archive entries accepted by the `filter` using given options
+    ///
+    /// See `ExtractOptions` for details
+    #[tracing::instrument(level = "debug", skip(self, filter))]
+    pub fn extract_by<T: Into<PathBuf> + std::fmt::Debug>(&mut self, folder: T, filter: impl Fn(&str) -> bool, options: &ExtractOptions) -> anyhow::Result<()> {
+        let folder = folder.into();
+        let policy = options.overwrite_policy;
+
+        self.extract_entries(folder.clone(), |name, mtime| filter(name) && policy.should_extract(folder.join(name), mtime), options)
+    }
+
+    /// Extract archive entries accepted by the `filter`
+    ///
+    /// `filter` is called with entries' names and modification times if they're stored in the archive
+    fn extract_entries(&mut self, folder: PathBuf, filter: impl Fn(&str, Option<SystemTime>) -> bool, options: &ExtractOptions) -> anyhow::Result<()> {
+        tracing::trace!("Extracting filtered archive entries");
+
+        match self {
+            Archive::Zip(_, zip) => {
+                for i in 0..zip.len() {
+                    let mut entry = zip.by_index(i)?;
+
+                    if !filter(entry.name(), entry.last_modified().map(zip_time)) {
+                        continue;
+                    }
+
+                    let Some(name) = entry.enclosed_name() else {
+                        tracing::warn!("Skipping archive entry with unsafe name: {}", entry.name());
+
+                        continue;
+                    };
+
+                    let path = folder.join(name);
+
+                    if entry.is_dir() {
+                        std::fs::create_dir_all(&path)?;
+
+                        continue;
+                    }
+
+                    if options.skip_identical && is_identical(&path, entry.size(), entry.crc32()) {
+                        continue;
+                    }
+
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+
+                    std::io::copy(&mut entry, &mut File::create(&path)?)?;
+
+                    if let Some(mode) = entry.unix_mode() {
+                        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+                    }
+                }
+            }
+
+            Archive::Tar(_, tar) => unpack_tar_filtered(tar, &folder, filter, options.skip_identical)?,
+            Archive::TarXz(_, tar) => unpack_tar_filtered(tar, &folder, filter, options.skip_identical)?,
+            Archive::TarGz(_, tar) => unpack_tar_filtered(tar, &folder, filter, options.skip_identical)?,
+            Archive::TarBz2(_, tar) => unpack_tar_filtered(tar, &folder, filter, options.skip_identical)?,
+            Archive::TarZst(_, tar) => unpack_tar_filtered(tar, &folder, filter, options.skip_identical)?,
+
+            Archive::SevenZ(archive) |
+            Archive::ZipMultipart(archive) => {
+                let archive = archive.clone();
+
+                let checksums = if options.skip_identical {
+                    seven_zip_checksums(&archive)?
+                } else {
+                    HashMap::new()
+                };
+
+                let entries = self.get_entries()?
+                    .into_iter()
+                    .filter(|entry| filter(&entry.name, None))
+                    .filter(|entry| {
+                        !checksums.get(&entry.name)
+                            .is_some_and(|(size, crc32)| is_identical(folder.join(&entry.name), *size, *crc32))
+                    })
+                    .map(|entry| entry.name)
+                    .collect::<Vec<_>>();
+
+                if entries.is_empty() {
+                    return Ok(());
+                }
+
+                // Entries are passed using the list file because there could be too many of them
+                let mut list_path = archive.as_os_str().to_owned();
+
+                list_path.push(".list");
+
+                let list_path = PathBuf::from(list_path);
+
+                std::fs::write(&list_path, entries.join("\n"))?;
+
+                let mut list = OsString::from("@");
+
+                list.push(list_path.as_os_str());
+
+                // Workaround to allow 7z to overwrite files
+                Command::new("chmod")
+                    .arg("-R")
+                    .arg("755")
+                    .arg(&folder)
+                    .output()?;
+
+                let mut output = OsString::from("-o");
+
+                output.push(folder.as_os_str());
+
+                let result = Command::new(get7z()?)
+                    .arg("x")
+                    .arg(&archive)
+                    .arg(output)
+                    .arg("-aoa")
+                    .args(options.low_memory.then_some("-mmt=1"))
+                    .arg(list)
+                    .output();
+
+                std::fs::remove_file(&list_path)?;
+
+                result?;
+
+                Command::new("chmod")
+                    .arg("-R")
+                    .arg("755")
+                    .arg(&folder)
+                    .output()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set modification times of the extracted entries accepted by the `filter`
+    /// to the ones stored in the archive
+    ///
+    /// Only zip entries are updated. Tar archives and 7z already preserve
+    /// modification times when they're extracted
+    #[tracing::instrument(level = "debug", skip(self, filter))]
+    pub fn restore_mtimes<T: Into<PathBuf> + std::fmt::Debug>(&mut self, folder: T, filter: impl Fn(&str) -> bool) -> anyhow::Result<()> {
+        let folder = folder.into();
+
+        if let Archive::Zip(_, zip) = self {
+            for i in 0..zip.len() {
+                let entry = zip.by_index_raw(i)?;
+
+                if !filter(entry.name()) {
+                    continue;
+                }
+
+                let (Some(name), Some(mtime)) = (entry.enclosed_name(), entry.last_modified()) else {
+                    continue;
+                };
+
+                let path = folder.join(name);
+
+                if path.exists() {
+                    File::open(&path)?.set_modified(zip_time(mtime))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert time of the zip entry to the system one
+///
+/// Zip archives don't store time zones, so the time is considered to be in UTC
+fn zip_time(time: zip::DateTime) -> SystemTime {
+    // Days since the unix epoch from the civil date
+    let (year, month) = if time.month() > 2 {
+        (time.year() as i64, time.month() as i64 - 3)
+    } else {
+        (time.year() as i64 - 1, time.month() as i64 + 9)
+    };
+
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * month + 2) / 5 + time.day() as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    let days = era * 146097 + day_of_era - 719468;
+
+    let seconds = days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64;
+
+    UNIX_EPOCH + Duration::from_secs(seconds.max(0) as u64)
+}
+
+/// Check if the file has given size and CRC32 checksum
+fn is_identical(path: impl AsRef<Path>, size: u64, crc32: u32) -> bool {
+    let path = path.as_ref();
+
+    if !path.symlink_metadata().is_ok_and(|metadata| metadata.is_file() && metadata.len() == size) {
+        return false;
+    }
+
+    let mut hasher = crc32fast::Hasher::new();
+
+    let hashed = File::open(path)
+        .and_then(|mut file| std::io::copy(&mut file, &mut HashWriter(&mut hasher)));
+
+    hashed.is_ok() && hasher.finalize() == crc32
+}
+
+/// Writer which updates CRC32 checksum by the written data
+struct HashWriter<'a>(&'a mut crc32fast::Hasher);
+
+impl Write for HashWriter<'_> {
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Get sizes and CRC32 checksums of the 7z archive entries
+fn seven_zip_checksums(archive: &Path) -> anyhow::Result<HashMap<String, (u64, u32)>> {
+    let output = Command::new(get7z()?)
+        .arg("l")
+        .arg("-slt")
+        .arg(archive)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()?;
+
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    // Technical information of the entries is printed after the archive's one
+    let Some((_, entries)) = output.split_once("\n----------") else {
+        anyhow::bail!("Failed to list archive entries: {:?}", archive);
+    };
+
+    let mut checksums = HashMap::new();
+
+    for block in entries.split("\n\n") {
+        let mut path = None;
+        let mut size = None;
+        let mut crc32 = None;
+
+        for line in block.lines() {
+            match line.split_once(" = ") {
+                Some(("Path", value)) => path = Some(value),
+                Some(("Size", value)) => size = value.parse::<u64>().ok(),
+                Some(("CRC", value)) => crc32 = u32::from_str_radix(value, 16).ok(),
+
+                _ => ()
+            }
+        }
+
+        if let (Some(path), Some(size), Some(crc32)) = (path, size, crc32) {
+            checksums.insert(path.to_string(), (size, crc32));
+        }
+    }
+
+    Ok(checksums)
+}
+
+/// Unpack tar archive entries accepted by the `filter`
+///
+/// If `skip_identical` is set, then entries are compared with already existing
+/// files of the same size, and only changed part of the file is written
+fn unpack_tar_filtered(tar: &mut TarArchive<impl Read>, folder: &Path, filter: impl Fn(&str, Option<SystemTime>) -> bool, skip_identical: bool) -> std::io::Result<()> {
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+
+        let mtime = entry.header().mtime()
+            .map(|mtime| UNIX_EPOCH + Duration::from_secs(mtime))
+            .ok();
+
+        let name = entry.path()?.into_owned();
+
+        if !filter(&name.to_string_lossy(), mtime) {
+            continue;
+        }
+
+        let path = folder.join(&name);
+
+        // Only regular files with safe paths can be compared, other entries are unpacked as usual
+        let comparable = skip_identical &&
+            entry.header().entry_type().is_file() &&
+            name.components().all(|component| matches!(component, std::path::Component::Normal(_))) &&
+            path.symlink_metadata().is_ok_and(|metadata| metadata.is_file() && metadata.len() == entry.size());
+
+        if !comparable {
+            entry.unpack_in(folder)?;
+        }
+
+        else if write_changed(&mut entry, &path)? {
+            if let Ok(mode) = entry.header().mode() {
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+            }
+
+            if let Some(mtime) = mtime {
+                File::options().write(true).open(&path)?.set_modified(mtime)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare the file with the reader's content and overwrite the file starting from the first changed chunk
+///
+/// Return `true` if the file was changed
+fn write_changed(reader: &mut impl Read, path: &Path) -> std::io::Result<bool> {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
+    let mut file = File::options().read(true).write(true).open(path)?;
+
+    let mut expected = Vec::with_capacity(CHUNK_SIZE as usize);
+    let mut actual = Vec::with_capacity(CHUNK_SIZE as usize);
+
+    let mut offset = 0;
+
+    loop {
+        expected.clear();
+        actual.clear();
+
+        let read = reader.by_ref().take(CHUNK_SIZE).read_to_end(&mut expected)?;
+
+        if read == 0 {
+            return Ok(false);
+        }
+
+        (&mut file).take(read as u64).read_to_end(&mut actual)?;
+
+        if expected != actual {
+            file.seek(std::io::SeekFrom::Start(offset))?;
+            file.write_all(&expected)?;
+
+            let len = offset + read as u64 + std::io::copy(reader, &mut file)?;
+
+            file.set_len(len)?;
+
+            return Ok(true);
+        }
+
+        offset += read as u64;
+    }
+}
+
+/// Check if the archive with given name can be extracted while it's being downloaded
+///
+/// Only zip and tar archives can be streamed. 7z and multipart archives need to be stored on the disk
+pub fn is_streamable(name: impl AsRef<str>) -> bool {
+    let name = name.as_ref();
+
+    [".zip", ".tar", ".tar.xz", ".tar.gz", ".tar.bz2", ".tar.zst"].iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+/// Extract archive with given name from the stream
+///
+/// Return total size of the extracted entries, or `None` if the archive can't be extracted
+/// from a stream. In this case nothing was extracted, and the archive should be downloaded
+/// to the disk. This can happen with zip archives which don't store entries sizes in their local headers
+#[tracing::instrument(level = "debug", skip(reader))]
+pub fn extract_stream(reader: impl Read, name: &str, folder: impl AsRef<Path> + std::fmt::Debug) -> anyhow::Result<Option<u64>> {
+    tracing::trace!("Extracting archive stream");
+
+    let folder = folder.as_ref();
+
+    let unpacked = if name.ends_with(".zip") {
+        let mut reader = BufReader::new(reader);
+        let mut unpacked = 0;
+
+        // Local file header: signature (4 bytes), version (2 bytes), flags (2 bytes)
+        // Flag's bit 3 means that sizes are stored after the compressed data
+        let header = reader.fill_buf()?;
+
+        if header.len() < 8 || header[6] & 0b1000 != 0 {
+            return Ok(None);
+        }
+
+        while let Some(mut entry) = zip::read::read_zipfile_from_stream(&mut reader)? {
+            let Some(name) = entry.enclosed_name() else {
+                tracing::warn!("Skipping archive entry with unsafe name: {}", entry.name());
+
+                continue;
+            };
+
+            let path = folder.join(name);
+
+            if entry.is_dir() {
+                std::fs::create_dir_all(&path)?;
+            }
+
+            else {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                unpacked += std::io::copy(&mut entry, &mut File::create(&path)?)?;
+            }
+        }
+
+        unpacked
+    }
+
+    else if name.ends_with(".tar.xz") {
+        unpack_tar(XzReader::new(reader), folder)?
+    }
+
+    else if name.ends_with(".tar.gz") {
+        unpack_tar(GzReader::new(reader), folder)?
+    }
+
+    else if name.ends_with(".tar.bz2") {
+        unpack_tar(Bz2Reader::new(reader), folder)?
+    }
+
+    else if name.ends_with(".tar.zst") {
+        unpack_tar(ZstdReader::new(reader)?, folder)?
+    }
+
+    else if name.ends_with(".tar") {
+        unpack_tar(reader, folder)?
+    }
+
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(unpacked))
+}
+
+fn unpack_tar(reader: impl Read, folder: &Path) -> std::io::Result<u64> {
+    let mut unpacked = 0;
+
+    for entry in TarArchive::new(reader).entries()? {
+        let mut entry = entry?;
+
+        unpacked += entry.size();
+
+        entry.unpack_in(folder)?;
+    }
+
+    Ok(unpacked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_format_from_magic() {
+        let mut tar = vec![0; 512];
+
+        tar[257..262].copy_from_slice(b"ustar");
+
+        assert_eq!(ArchiveFormat::from_magic(b"PK\x03\x04\x14\x00"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_magic(b"PK\x05\x06\x00\x00"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_magic(b"7z\xBC\xAF\x27\x1C\x00\x04"), Some(ArchiveFormat::SevenZ));
+        assert_eq!(ArchiveFormat::from_magic(b"\xFD7zXZ\x00\x00\x04"), Some(ArchiveFormat::TarXz));
+        assert_eq!(ArchiveFormat::from_magic(b"\x1F\x8B\x08\x00"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_magic(b"BZh91AY&SY"), Some(ArchiveFormat::TarBz2));
+        assert_eq!(ArchiveFormat::from_magic(b"\x28\xB5\x2F\xFD\x04\x58"), Some(ArchiveFormat::TarZst));
+        assert_eq!(ArchiveFormat::from_magic(&tar), Some(ArchiveFormat::Tar));
+
+        assert_eq!(ArchiveFormat::from_magic(b"<!DOCTYPE html>"), None);
+        assert_eq!(ArchiveFormat::from_magic(b""), None);
+    }
+
+    #[test]
+    fn test_truncated_zip() {
+        let path = std::env::temp_dir().join(format!(".anime-game-core-test-truncated-{}.zip", std::process::id()));
+
+        // Local file header without the rest of the archive
+        std::fs::write(&path, b"PK\x03\x04\x14\x00\x00\x00\x08\x00").unwrap();
+
+        let err = Archive::open(&path)
+            .and_then(|mut archive| archive.get_entries())
+            .unwrap_err();
+
+        assert!(err.downcast_ref::<UnsupportedArchive>().is_none());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_restore_mtimes() {
+        use std::io::Write;
+
+        let folder = std::env::temp_dir().join(format!(".anime-game-core-test-mtimes-{}", std::process::id()));
+        let path = folder.with_extension("zip");
+
+        let options = zip::write::SimpleFileOptions::default()
+            .last_modified_time(zip::DateTime::from_date_and_time(2020, 1, 2, 3, 4, 6).unwrap());
+
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+
+        zip.start_file("file.txt", options).unwrap();
+        zip.write_all(b"Hello, World!").unwrap();
+        zip.finish().unwrap();
+
+        let mut archive = Archive::open(&path).unwrap();
+
+        archive.extract(&folder).unwrap();
+        archive.restore_mtimes(&folder, |_| true).unwrap();
+
+        let mtime = folder.join("file.txt").metadata().unwrap().modified().unwrap();
+
+        assert_eq!(mtime, UNIX_EPOCH + Duration::from_secs(1577934246));
+
+        std::fs::remove_dir_all(folder).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        use std::io::Write;
+
+        let folder = std::env::temp_dir().join(format!(".anime-game-core-test-overwrite-{}", std::process::id()));
+        let path = folder.with_extension("zip");
+
+        let options = zip::write::SimpleFileOptions::default()
+            .last_modified_time(zip::DateTime::from_date_and_time(2020, 1, 2, 3, 4, 6).unwrap());
+
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+
+        for name in ["older.txt", "newer.txt", "missing.txt"] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(b"archive").unwrap();
+        }
+
+        zip.finish().unwrap();
+
+        std::fs::create_dir_all(&folder).unwrap();
+
+        for (name, mtime) in [("older.txt", 1500000000), ("newer.txt", 1600000000)] {
+            std::fs::write(folder.join(name), b"local").unwrap();
+
+            File::options().write(true).open(folder.join(name)).unwrap()
+                .set_modified(UNIX_EPOCH + Duration::from_secs(mtime))
+                .unwrap();
+        }
+
+        Archive::open(&path).unwrap()
+            .extract_with_policy(&folder, OverwritePolicy::OverwriteIfNewer)
+            .unwrap();
+
+        assert_eq!(std::fs::read(folder.join("older.txt")).unwrap(), b"archive");
+        assert_eq!(std::fs::read(folder.join("newer.txt")).unwrap(), b"local");
+        assert_eq!(std::fs::read(folder.join("missing.txt")).unwrap(), b"archive");
+
+        std::fs::write(folder.join("older.txt"), b"local").unwrap();
+
+        Archive::open(&path).unwrap()
+            .extract_with_policy(&folder, OverwritePolicy::SkipIfExists)
+            .unwrap();
+
+        assert_eq!(std::fs::read(folder.join("older.txt")).unwrap(), b"local");
+
+        std::fs::remove_dir_all(folder).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_tar_zst() {
+        let folder = std::env::temp_dir().join(format!(".anime-game-core-test-zst-{}", std::process::id()));
+        let path = folder.with_extension("tar.zst");
+
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+
+        header.set_size(13);
+        header.set_mode(0o644);
+        header.set_cksum();
+
+        tar.append_data(&mut header, "folder/file.txt", &b"Hello, World!"[..]).unwrap();
+
+        let tar = tar.into_inner().unwrap();
+
+        std::fs::write(&path, zstd::encode_all(tar.as_slice(), 0).unwrap()).unwrap();
+
+        let mut archive = Archive::open(&path).unwrap();
+
+        assert!(matches!(archive, Archive::TarZst(_, _)));
+
+        archive.extract(&folder).unwrap();
+
+        assert_eq!(std::fs::read(folder.join("folder/file.txt")).unwrap(), b"Hello, World!");
+
+        std::fs::remove_dir_all(&folder).unwrap();
+        std::fs::create_dir_all(&folder).unwrap();
+
+        let unpacked = extract_stream(File::open(&path).unwrap(), "file.tar.zst", &folder).unwrap();
+
+        assert_eq!(unpacked, Some(13));
+        assert_eq!(std::fs::read(folder.join("folder/file.txt")).unwrap(), b"Hello, World!");
+
+        std::fs::remove_dir_all(folder).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_skip_identical() {
+        let folder = std::env::temp_dir().join(format!(".anime-game-core-test-identical-{}", std::process::id()));
+        let zip_path = folder.with_extension("zip");
+        let tar_path = folder.with_extension("tar");
+
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        let mut tar = tar::Builder::new(File::create(&tar_path).unwrap());
+
+        for (name, content) in [("same.txt", b"archive"), ("changed.txt", b"archive")] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+
+            let mut header = tar::Header::new_gnu();
+
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+
+            tar.append_data(&mut header, name, &content[..]).unwrap();
+        }
+
+        zip.finish().unwrap();
+        tar.finish().unwrap();
+
+        let options = ExtractOptions::default()
+            .with_skip_identical(true);
+
+        let old_mtime = UNIX_EPOCH + Duration::from_secs(1500000000);
+
+        for path in [&zip_path, &tar_path] {
+            std::fs::create_dir_all(&folder).unwrap();
+            std::fs::write(folder.join("same.txt"), b"archive").unwrap();
+            std::fs::write(folder.join("changed.txt"), b"arch1ve").unwrap();
+
+            File::options().write(true).open(folder.join("same.txt")).unwrap()
+                .set_modified(old_mtime)
+                .unwrap();
+
+            Archive::open(path).unwrap()
+                .extract_by(&folder, |_| true, &options)
+                .unwrap();
+
+            // Identical file must not be written
+            assert_eq!(folder.join("same.txt").metadata().unwrap().modified().unwrap(), old_mtime);
+            assert_eq!(std::fs::read(folder.join("changed.txt")).unwrap(), b"archive");
+
+            std::fs::remove_dir_all(&folder).unwrap();
+        }
+
+        std::fs::remove_file(zip_path).unwrap();
+        std::fs::remove_file(tar_path).unwrap();
+    }
 }
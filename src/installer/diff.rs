@@ -0,0 +1,776 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use md5::{Md5, Digest};
+
+use crate::version::Version;
+use crate::traits::version_diff::VersionDiffExt;
+use crate::repairer::IntegrityFile;
+
+use super::downloader::DownloadingError;
+use super::installer::Update as InstallerUpdate;
+use super::free_space;
+use super::archives::{Archive, ExtractOptions};
+use super::options::InstallOptions;
+use super::report::InstallReport;
+use super::patcher::HdiffPatchError;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiffUpdate {
+    CheckingFreeSpace(PathBuf),
+
+    /// Temp folder is stored on another filesystem than the installation folder,
+    /// so downloaded files have to be copied instead of being moved
+    ///
+    /// See `InstallOptions::relocate_temp`
+    ///
+    /// `(temp folder, installation folder)`
+    TempOnAnotherFilesystem(PathBuf, PathBuf),
+
+    InstallerUpdate(InstallerUpdate),
+
+    ApplyingHdiffStarted,
+    ApplyingHdiffProgress(u64, u64),
+    ApplyingHdiffFinished,
+
+    RemovingOutdatedStarted,
+    RemovingOutdatedProgress(u64, u64),
+
+    /// Outdated file listed in the `deletefiles.txt` doesn't exist
+    ///
+    /// `(path)`
+    RemovingOutdatedSkipped(PathBuf),
+
+    RemovingOutdatedFinished,
+
+    /// Installed files are being verified and repaired by the `Game::ensure_installed` method
+    RepairingStarted,
+    RepairingProgress(u64, u64),
+    RepairingFinished
+}
+
+impl From<InstallerUpdate> for DiffUpdate {
+    #[inline]
+    fn from(update: InstallerUpdate) -> Self {
+        Self::InstallerUpdate(update)
+    }
+}
+
+impl DiffUpdate {
+    /// Estimate overall installation progress in `[0.0, 1.0]` range
+    ///
+    /// Installation phases have heuristic weights: downloading takes 60% of the progress,
+    /// unpacking 25%, applying hdiff patches 10% and removing outdated files 5%
+    ///
+    /// Return `None` if the update doesn't report progress
+    pub fn overall_progress(&self) -> Option<f32> {
+        let phase = |start: f32, weight: f32, current: u64, total: u64| {
+            let fraction = if total > 0 {
+                (current as f32 / total as f32).min(1.0)
+            } else {
+                1.0
+            };
+
+            start + weight * fraction
+        };
+
+        match self {
+            Self::InstallerUpdate(InstallerUpdate::DownloadingProgress(current, total)) => Some(phase(0.0, 0.6, *current, *total)),
+            Self::InstallerUpdate(InstallerUpdate::DownloadingFinished) => Some(0.6),
+
+            Self::InstallerUpdate(InstallerUpdate::UnpackingProgress(current, total)) => Some(phase(0.6, 0.25, *current, *total)),
+            Self::InstallerUpdate(InstallerUpdate::UnpackingFinished) => Some(0.85),
+
+            Self::ApplyingHdiffProgress(current, total) => Some(phase(0.85, 0.1, *current, *total)),
+            Self::ApplyingHdiffFinished => Some(0.95),
+
+            Self::RemovingOutdatedProgress(current, total) => Some(phase(0.95, 0.05, *current, *total)),
+            Self::RemovingOutdatedFinished => Some(1.0),
+
+            _ => None
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiffDownloadingError {
+    /// Your installation is already up to date and not needed to be updated
+    #[error("Component version is already latest")]
+    AlreadyLatest,
+
+    /// Current version is too outdated and can't be updated.
+    /// It means that you have to download everything from zero
+    #[error("Components version is too outdated and can't be updated")]
+    Outdated,
+
+    /// When there's multiple urls and you can't save them as a single file
+    #[error("Component has multiple downloading urls and can't be saved as a single file")]
+    MultipleSegments,
+
+    /// Failed to fetch remove data. Redirected from `Downloader`
+    #[error("{0}")]
+    DownloadingError(DownloadingError),
+
+    /// Server responded with an error status code, e.g. 403 if
+    /// downloading is blocked in the region or 404 if the package doesn't exist
+    ///
+    /// `(status code, url)`
+    #[error("Server responded with status code {0}: {1}")]
+    HttpStatus(u16, String),
+
+    /// Failed to apply hdiff patch
+    #[error("{0}")]
+    HdiffPatch(HdiffPatchError),
+
+    /// Tool used to apply hdiff patches is not available,
+    /// e.g. the `hpatchz` binary is missing or can't be executed
+    ///
+    /// See `hpatchz::set_binary_path` to use a different binary
+    /// or `InstallOptions::patcher` to use a different tool
+    #[error("hdiff patcher is missing or can't be executed")]
+    HpatchzMissing,
+
+    /// Downloaded archive's MD5 hash differs from the expected one
+    ///
+    /// `(path, expected, got)`
+    #[error("Downloaded archive {0:?} is corrupted: expected MD5 {1}, got {2}")]
+    ChecksumMismatch(PathBuf, String, String),
+
+    /// Downloaded archive can't be opened or its entries can't be read
+    ///
+    /// `(path, error message)`
+    #[error("Downloaded archive {0:?} is corrupted: {1}")]
+    CorruptArchive(PathBuf, String),
+
+    /// Downloaded archive's format is not supported
+    #[error("Archive format is not supported: {0:?}")]
+    UnsupportedArchive(PathBuf),
+
+    /// Failed to extract the downloaded archive
+    ///
+    /// `(path, error message)`
+    #[error("Failed to extract archive {0:?}: {1}")]
+    Unpacking(PathBuf, String),
+
+    /// Failed to remove some file
+    ///
+    /// `(path, error message)`
+    #[error("Failed to remove file {0:?}: {1}")]
+    FileRemoveFailed(PathBuf, String),
+
+    /// Failed to rename some file
+    ///
+    /// `(path, error message)`
+    #[error("Failed to rename file {0:?}: {1}")]
+    FileRenameFailed(PathBuf, String),
+
+    /// Failed to read installation manifest
+    ///
+    /// `(path, error message)`
+    #[error("Failed to read installation manifest {0:?}: {1}")]
+    ManifestReadFailed(PathBuf, String),
+
+    /// Path listed in the installation manifests leads outside of the installation folder
+    #[error("Path is outside of the installation folder: {0:?}")]
+    UnsafePath(PathBuf),
+
+    /// Failed to save file before changing it in the transactional installation mode
+    ///
+    /// `(path, error message)`
+    #[error("Failed to backup file {0:?}: {1}")]
+    BackupFailed(PathBuf, String),
+
+    /// File which should be patched has different MD5 hash than the hdiff patch expects
+    ///
+    /// `(path, expected, got)`
+    #[error("File {0:?} can't be patched because it's corrupted: expected MD5 {1}, got {2}")]
+    SourceMismatch(PathBuf, String, String),
+
+    /// Temp folder can't be used to store downloaded archives,
+    /// e.g. it's read-only or not mounted
+    ///
+    /// See `InstallOptions::temp_fallback` to use another folder in this case
+    ///
+    /// `(path, reason)`
+    #[error("Temp folder {0:?} can't be used: {1}")]
+    TempUnavailable(PathBuf, String),
+
+    /// Installation was stopped by the cancel flag. Already processed
+    /// files are kept in place so installation can be continued later
+    #[error("Installation was cancelled")]
+    Cancelled,
+
+    /// Differences of the chain can't be installed one after another
+    ///
+    /// `(version the previous difference updates to, current version of the next difference)`
+    #[error("Differences chain is broken: expected difference from {0}, got from {1:?}")]
+    BrokenChain(Version, Option<Version>),
+
+    /// Installation has unexpected version after the difference was installed
+    ///
+    /// `(expected version, installed version)`
+    #[error("Expected version {0} after installing the difference, got {1:?}")]
+    VersionMismatch(Version, Option<Version>),
+
+    /// Downloaded archive was made for another version than the difference updates to
+    ///
+    /// `(path, archive version, difference version)`
+    #[error("Downloaded archive {0:?} updates to version {1}, expected {2}")]
+    ArchiveVersionMismatch(PathBuf, Version, Version),
+
+    /// Installation path wasn't specified. This could happen when you
+    /// try to call `install` method on `VersionDiff` that was generated
+    /// in `VoicePackage::list_latest`. This method couldn't know
+    /// your game installation path and thus indicates that it doesn't know
+    /// where this package needs to be installed
+    #[error("Path to the component's downloading folder is not specified")]
+    PathNotSpecified
+}
+
+impl From<DownloadingError> for DiffDownloadingError {
+    fn from(error: DownloadingError) -> Self {
+        match error {
+            DownloadingError::HttpStatus(code, url) => Self::HttpStatus(code, url),
+
+            error => Self::DownloadingError(error)
+        }
+    }
+}
+
+impl From<minreq::Error> for DiffDownloadingError {
+    fn from(error: minreq::Error) -> Self {
+        DownloadingError::Minreq(error.to_string()).into()
+    }
+}
+
+/// Archive of the version difference downloaded by the `VersionDiff::download_archive_to` method
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadedArchive {
+    /// Path to the downloaded archive
+    pub path: PathBuf,
+
+    /// MD5 hash of the archive if it's known
+    pub checksum: Option<String>,
+
+    /// Version the archive updates the game to
+    pub version: Version
+}
+
+/// Ordered list of version differences which should be installed one after another
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffChain<T> {
+    pub diffs: Vec<T>,
+
+    /// Total size of the archives of all the differences
+    pub downloaded_size: u64,
+
+    /// Total size of the unpacked data of all the differences
+    pub unpacked_size: u64
+}
+
+/// Version difference which can be installed by the `install_many` and `install_chain` functions
+pub trait InstallableDiff: VersionDiffExt<Error = DiffDownloadingError, Update = DiffUpdate> + Send {
+    /// Return currently selected temp folder path
+    fn temp_folder(&self) -> PathBuf;
+
+    /// Get `.version` file path
+    fn version_file_path(&self) -> Option<PathBuf>;
+
+    /// Try to download the diff into the specified folder using given options
+    /// to install it later using the `install_downloaded` method
+    fn download_archive_to(&mut self, folder: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) + Send + 'static) -> Result<DownloadedArchive, DiffDownloadingError>;
+
+    /// Install the archive downloaded by the `download_archive_to` method
+    fn install_downloaded(&self, archive: &DownloadedArchive, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError>;
+
+    /// Try to install the difference by given location using specified options
+    fn install_to_by(&self, path: impl AsRef<Path>, options: &InstallOptions, updater: impl Fn(DiffUpdate) + Clone + Send + 'static) -> Result<InstallReport, DiffDownloadingError>;
+}
+
+/// Find chain of the version differences from the `start` version to the `target` one
+///
+/// `steps` are `(from, to, patch)` values of the patches listed by the API. On every step the patch
+/// updating the game to the newest version not exceeding the `target` is chosen, and the difference
+/// is made from it by the `diff` function
+///
+/// Return `DiffDownloadingError::Outdated` if the chain can't be formed
+pub(crate) fn find_chain<'a, P, T: VersionDiffExt>(start: Version, target: Version, steps: &'a [(Version, Version, P)], mut diff: impl FnMut(Version, Version, &'a P) -> T) -> Result<DiffChain<T>, DiffDownloadingError> {
+    if start >= target {
+        return Err(DiffDownloadingError::AlreadyLatest);
+    }
+
+    let mut chain = DiffChain {
+        diffs: Vec::new(),
+        downloaded_size: 0,
+        unpacked_size: 0
+    };
+
+    let mut current = start;
+
+    while current < target {
+        let step = steps.iter()
+            .filter(|(from, to, _)| *from == current && *to <= target)
+            .max_by_key(|(_, to, _)| *to);
+
+        let Some((_, latest, patch)) = step else {
+            tracing::debug!("Failed to find difference from {current} to {target}");
+
+            return Err(DiffDownloadingError::Outdated);
+        };
+
+        let diff = diff(current, *latest, patch);
+
+        chain.downloaded_size += diff.downloaded_size().unwrap_or_default();
+        chain.unpacked_size += diff.unpacked_size().unwrap_or_default();
+
+        chain.diffs.push(diff);
+
+        current = *latest;
+    }
+
+    Ok(chain)
+}
+
+/// Install multiple differences (e.g. voice packages) to the same location
+///
+/// Archives of all the differences are downloaded in parallel, and then the differences
+/// are installed one after another in the given order because their installation manifests
+/// are stored in the same place. Differences which have multiple segments are downloaded
+/// during their installation
+///
+/// Speed limit of the options is shared between all the downloads. Failure
+/// of one difference doesn't stop others, so results are returned in the same order
+/// as the given differences
+///
+/// `progress` is called with `(downloaded bytes, total bytes)` values of all the differences
+pub fn install_many<T: InstallableDiff>(mut diffs: Vec<T>, path: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) + Send + Sync + 'static) -> Vec<Result<InstallReport, DiffDownloadingError>> {
+    let path = path.as_ref();
+
+    let total = diffs.iter()
+        .flat_map(|diff| diff.downloaded_size())
+        .sum::<u64>();
+
+    let mut download_options = options.clone();
+
+    download_options.speed_limit = options.speed_limit
+        .map(|limit| (limit / diffs.len().max(1) as u64).max(1));
+
+    // Archives of different differences can have the same names,
+    // so every difference is downloaded to its own temp subfolder
+    let folders = diffs.iter()
+        .map(|diff| {
+            let temp_folder = options.temp_folder.clone()
+                .unwrap_or_else(|| diff.temp_folder());
+
+            let hash = format!("{:x}", Md5::digest(diff.downloading_uri().unwrap_or_default()));
+
+            (temp_folder.join(format!(".anime-game-core-{}", &hash[..16])), temp_folder)
+        })
+        .collect::<Vec<_>>();
+
+    // All the archives are stored in the temp folders at the same time
+    let mut required = HashMap::<&Path, u64>::new();
+
+    for (diff, (_, temp_folder)) in diffs.iter().zip(&folders) {
+        *required.entry(temp_folder.as_path()).or_default() += diff.downloaded_size().unwrap_or_default();
+    }
+
+    let no_space = required.into_iter()
+        .filter_map(|(temp_folder, required)| {
+            let space = free_space::available(temp_folder)?;
+
+            (space < required).then(|| (temp_folder.to_path_buf(), (required, space)))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let downloaded = Arc::new(Mutex::new(vec![0; diffs.len()]));
+    let progress = Arc::new(progress);
+
+    let updater = |i: usize| {
+        let downloaded = downloaded.clone();
+        let progress = progress.clone();
+
+        move |current: u64| {
+            let mut downloaded = downloaded.lock().unwrap();
+
+            downloaded[i] = current;
+
+            (progress)(downloaded.iter().sum(), total);
+        }
+    };
+
+    let archives = std::thread::scope(|scope| {
+        let handles = diffs.iter_mut().zip(&folders).enumerate().map(|(i, (diff, (folder, temp_folder)))| {
+            let updater = updater(i);
+            let options = &download_options;
+            let no_space = &no_space;
+
+            scope.spawn(move || {
+                if let Some((required, space)) = no_space.get(temp_folder) {
+                    tracing::error!("No free space available in the temp folder. Required: {required}. Available: {space}");
+
+                    return Err(DownloadingError::NoSpaceAvailable(temp_folder.to_path_buf(), *required, *space).into());
+                }
+
+                // Differences without a single archive are downloaded by the installation
+                if diff.downloading_uri().is_none() {
+                    return Ok(None);
+                }
+
+                if let Err(err) = std::fs::create_dir_all(folder) {
+                    return Err(DiffDownloadingError::TempUnavailable(folder.to_path_buf(), err.to_string()));
+                }
+
+                diff.download_archive_to(folder, options, move |current, _| updater(current))
+                    .map(Some)
+            })
+        }).collect::<Vec<_>>();
+
+        handles.into_iter()
+            .map(|handle| handle.join().expect("Failed to join downloading thread"))
+            .collect::<Vec<_>>()
+    });
+
+    diffs.iter().zip(archives).zip(folders).enumerate()
+        .map(|(i, ((diff, archive), (folder, _)))| {
+            let result = match archive {
+                Ok(Some(archive)) => diff.install_downloaded(&archive, path, options, |_| {}),
+
+                Ok(None) => {
+                    let updater = updater(i);
+
+                    diff.install_to_by(path, options, move |update| {
+                        if let DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(current, _)) = update {
+                            updater(current);
+                        }
+                    })
+                }
+
+                Err(err) => Err(err)
+            };
+
+            // Subfolder is kept if it still stores the archive
+            let _ = std::fs::remove_dir(folder);
+
+            result
+        })
+        .collect()
+}
+
+/// Install the differences to the same location one after another, e.g. from the `DiffChain`
+///
+/// Every difference must update the game from the version the previous one updates it to.
+/// Installed version is verified after every step, and the chain is stopped on the first error
+///
+/// `progress` is called with `(downloaded bytes, total bytes)` values of the whole chain
+pub fn install_chain<T: InstallableDiff>(diffs: Vec<T>, path: impl AsRef<Path>, options: &InstallOptions, progress: impl Fn(u64, u64) + Send + Sync + 'static) -> Result<Vec<InstallReport>, DiffDownloadingError> {
+    let path = path.as_ref();
+
+    for (prev, next) in diffs.iter().zip(diffs.iter().skip(1)) {
+        if next.current() != Some(prev.latest()) {
+            return Err(DiffDownloadingError::BrokenChain(prev.latest(), next.current()));
+        }
+    }
+
+    let total = diffs.iter()
+        .flat_map(|diff| diff.downloaded_size())
+        .sum::<u64>();
+
+    let progress = Arc::new(progress);
+
+    let mut downloaded = 0;
+    let mut reports = Vec::with_capacity(diffs.len());
+
+    for diff in diffs {
+        tracing::debug!("Installing difference from {:?} to {}", diff.current(), diff.latest());
+
+        let progress = progress.clone();
+
+        let result = diff.install_to_by(path, options, move |update| {
+            if let DiffUpdate::InstallerUpdate(InstallerUpdate::DownloadingProgress(current, _)) = update {
+                (progress)(downloaded + current, total);
+            }
+        });
+
+        let report = match result {
+            Ok(report) => report,
+
+            Err(err) => {
+                tracing::error!("Failed to install difference from {:?} to {}: {err}", diff.current(), diff.latest());
+
+                return Err(err);
+            }
+        };
+
+        let version_path = diff.version_file_path()
+            .unwrap_or_else(|| path.join(".version"));
+
+        let installed = std::fs::read(version_path).ok()
+            .filter(|version| version.len() >= 3)
+            .map(|version| Version::new(version[0], version[1], version[2]));
+
+        if installed != Some(diff.latest()) {
+            return Err(DiffDownloadingError::VersionMismatch(diff.latest(), installed));
+        }
+
+        downloaded += diff.downloaded_size().unwrap_or_default();
+
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+/// Compare MD5 hash of the downloaded archive with the expected one
+///
+/// Corrupted archive is removed so it will be downloaded again next time
+pub(crate) fn verify_checksum(path: &Path, expected: Option<&str>) -> Result<(), DiffDownloadingError> {
+    let Some(expected) = expected.filter(|checksum| !checksum.is_empty()) else {
+        return Ok(());
+    };
+
+    tracing::debug!("Verifying downloaded archive checksum");
+
+    let checksum = match crate::repairer::file_md5(path) {
+        Ok(checksum) => checksum,
+        Err(err) => return Err(DownloadingError::OutputFileError(path.to_path_buf(), err.to_string()).into())
+    };
+
+    if !checksum.eq_ignore_ascii_case(expected) {
+        tracing::error!("Downloaded archive is corrupted. Expected MD5: {expected}. Got: {checksum}");
+
+        #[allow(unused_must_use)] {
+            std::fs::remove_file(path);
+        }
+
+        return Err(DiffDownloadingError::ChecksumMismatch(path.to_path_buf(), expected.to_string(), checksum));
+    }
+
+    Ok(())
+}
+
+/// Verify MD5 hash of the archive split into parts
+///
+/// Parts are hashed in the given order as one file. All of them are removed if the hash doesn't match
+pub(crate) fn verify_split_checksum(parts: &[PathBuf], expected: Option<&str>) -> Result<(), DiffDownloadingError> {
+    let (Some(expected), Some(first_part)) = (expected.filter(|checksum| !checksum.is_empty()), parts.first()) else {
+        return Ok(());
+    };
+
+    tracing::debug!("Verifying checksum of the split archive");
+
+    let mut hasher = Md5::new();
+
+    for part in parts {
+        let result = std::fs::File::open(part)
+            .and_then(|mut file| std::io::copy(&mut file, &mut hasher));
+
+        if let Err(err) = result {
+            return Err(DownloadingError::OutputFileError(part.to_path_buf(), err.to_string()).into());
+        }
+    }
+
+    let checksum = format!("{:x}", hasher.finalize());
+
+    if !checksum.eq_ignore_ascii_case(expected) {
+        tracing::error!("Downloaded split archive is corrupted. Expected MD5: {expected}. Got: {checksum}");
+
+        for part in parts {
+            #[allow(unused_must_use)] {
+                std::fs::remove_file(part);
+            }
+        }
+
+        return Err(DiffDownloadingError::ChecksumMismatch(first_part.to_path_buf(), expected.to_string(), checksum));
+    }
+
+    Ok(())
+}
+
+/// Check that downloaded archives can be stored in the temp folder
+///
+/// Temp folder is created if it doesn't exist
+pub(crate) fn check_temp_folder(temp_folder: &Path, path: &Path, downloaded_size: u64, unpacked_size: u64) -> Result<(), DiffDownloadingError> {
+    // Try to write a file to make sure the folder is not read-only
+    let probe = temp_folder.join(".anime-game-core-probe");
+
+    let writable = std::fs::create_dir_all(temp_folder)
+        .and_then(|_| std::fs::write(&probe, []))
+        .and_then(|_| std::fs::remove_file(&probe));
+
+    if let Err(err) = writable {
+        return Err(DiffDownloadingError::TempUnavailable(temp_folder.to_path_buf(), err.to_string()));
+    }
+
+    // Check available free space for archive itself
+    let Some(space) = free_space::available(temp_folder) else {
+        return Err(DiffDownloadingError::TempUnavailable(temp_folder.to_path_buf(), String::from("path is not mounted")));
+    };
+
+    // We can possibly store downloaded archive + unpacked data on the same disk
+    let required = if free_space::is_same_disk(temp_folder, path) {
+        downloaded_size + unpacked_size
+    } else {
+        downloaded_size
+    };
+
+    if space < required {
+        return Err(DownloadingError::NoSpaceAvailable(temp_folder.to_path_buf(), required, space).into());
+    }
+
+    Ok(())
+}
+
+/// Select the folder to store downloaded archives in
+///
+/// Installation folder and system temp folder are tried after the given one if `temp_fallback`
+/// option is set. Installation folder is used instead of the temp folder stored on another
+/// filesystem if `relocate_temp` option is set
+pub(crate) fn select_temp_folder(temp_folder: PathBuf, path: &Path, downloaded_size: u64, unpacked_size: u64, options: &InstallOptions, updater: &impl Fn(DiffUpdate)) -> Result<PathBuf, DiffDownloadingError> {
+    let mut candidates = vec![temp_folder];
+
+    // System temp folder is often stored in RAM, so it's tried last
+    if options.temp_fallback {
+        candidates.push(path.to_path_buf());
+        candidates.push(std::env::temp_dir());
+
+        candidates.dedup();
+    }
+
+    let mut selected = None;
+    let mut first_error = None;
+
+    for candidate in candidates {
+        (updater)(DiffUpdate::CheckingFreeSpace(candidate.clone()));
+
+        match check_temp_folder(&candidate, path, downloaded_size, unpacked_size) {
+            Ok(()) => {
+                selected = Some(candidate);
+
+                break;
+            }
+
+            Err(err) => {
+                tracing::warn!("Temp folder {:?} can't be used: {err}", candidate);
+
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    let temp_folder = match (selected, first_error) {
+        (Some(temp_folder), _) => temp_folder,
+        (None, Some(err)) => return Err(err),
+        (None, None) => unreachable!()
+    };
+
+    if free_space::is_same_filesystem(&temp_folder, path) {
+        return Ok(temp_folder);
+    }
+
+    tracing::warn!("Temp folder {:?} is stored on another filesystem than the installation folder {:?}", temp_folder, path);
+
+    (updater)(DiffUpdate::TempOnAnotherFilesystem(temp_folder.clone(), path.to_path_buf()));
+
+    if !options.relocate_temp {
+        return Ok(temp_folder);
+    }
+
+    match check_temp_folder(path, path, downloaded_size, unpacked_size) {
+        Ok(()) => Ok(path.to_path_buf()),
+
+        Err(err) => {
+            tracing::warn!("Installation folder can't be used to store downloaded archives: {err}");
+
+            Ok(temp_folder)
+        }
+    }
+}
+
+/// Check if the archive is already downloaded and has expected size and MD5 hash
+pub(crate) fn is_complete_archive(path: &Path, size: u64, checksum: Option<&str>) -> bool {
+    let Some(checksum) = checksum else {
+        return false;
+    };
+
+    match path.metadata() {
+        Ok(metadata) if metadata.len() == size => crate::repairer::file_md5(path)
+            .map(|hash| hash.eq_ignore_ascii_case(checksum))
+            .unwrap_or(false),
+
+        _ => false
+    }
+}
+
+/// Result of the hdiff patch sent by the hdiff workers
+pub(crate) enum PatchResult {
+    Patched(PathBuf),
+    Skipped(PathBuf),
+
+    /// `(path, error message)`
+    Failed(PathBuf, String)
+}
+
+/// Check that the patcher has created expected file
+///
+/// Size and MD5 hash are compared only if the integrity entry is given
+pub(crate) fn verify_patched_file(file: &Path, output: &Path, integrity: Option<&IntegrityFile>) -> Result<(), HdiffPatchError> {
+    let failed = |stderr: String| HdiffPatchError {
+        file: file.to_path_buf(),
+        exit_code: None,
+        stderr
+    };
+
+    let metadata = output.metadata()
+        .map_err(|err| failed(format!("Patched file is missing: {err}")))?;
+
+    let Some(integrity) = integrity else {
+        return Ok(());
+    };
+
+    if metadata.len() != integrity.size {
+        return Err(failed(format!("Patched file has wrong size. Expected: {}. Got: {}", integrity.size, metadata.len())));
+    }
+
+    let checksum = crate::repairer::file_md5(output)
+        .map_err(|err| failed(err.to_string()))?;
+
+    if !checksum.eq_ignore_ascii_case(&integrity.md5) {
+        return Err(failed(format!("Patched file has wrong MD5 hash. Expected: {}. Got: {checksum}", integrity.md5)));
+    }
+
+    Ok(())
+}
+
+/// Extract archive entries allowed by the `extract_prefixes` install option
+///
+/// Modification times of the extracted files are restored if `preserve_mtime` is set
+pub(crate) fn extract_archive(archive: &mut Archive, folder: &Path, options: &InstallOptions) -> anyhow::Result<()> {
+    if options.low_memory || options.skip_identical {
+        let extract_options = ExtractOptions::default()
+            .with_low_memory(options.low_memory)
+            .with_skip_identical(options.skip_identical);
+
+        archive.extract_by(folder, |name| options.is_included(name), &extract_options)?;
+    }
+
+    else if options.extract_prefixes.is_none() {
+        archive.extract(folder)?;
+    }
+
+    else {
+        archive.extract_filtered(folder, |name| options.is_included(name))?;
+    }
+
+    if options.preserve_mtime {
+        if let Err(err) = archive.restore_mtimes(folder, |name| options.is_included(name)) {
+            tracing::warn!("Failed to restore modification times of the unpacked files: {err}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+use std::time::Duration;
+
+use super::archives::{Archive, Entry, ExtractOptions};
+
+/// Tool used by the `Installer` to unpack downloaded archives
+pub trait ArchiveExtractor: std::fmt::Debug + Send + Sync {
+    /// Get list of the archive entries
+    ///
+    /// Used to prepare the unpacking folder before extraction.
+    /// Default implementation reads them using `Archive`
+    fn entries(&self, archive: &Path) -> anyhow::Result<Vec<Entry>> {
+        Archive::open(archive)?.get_entries()
+    }
+
+    /// Extract the archive to the folder
+    ///
+    /// `progress` is called with `(unpacked bytes, total bytes)`
+    fn extract(&self, archive: &Path, folder: &Path, options: &ExtractOptions, progress: &dyn Fn(u64, u64)) -> anyhow::Result<()>;
+}
+
+/// Default extractor which unpacks the archive using `Archive` based on its detected format
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultExtractor;
+
+impl ArchiveExtractor for DefaultExtractor {
+    fn extract(&self, archive: &Path, folder: &Path, options: &ExtractOptions, progress: &dyn Fn(u64, u64)) -> anyhow::Result<()> {
+        let entries = Archive::open(archive)?.get_entries()?;
+
+        let total = entries.iter()
+            .map(|entry| entry.size.get_size())
+            .sum::<u64>();
+
+        let mut entries = entries.into_iter()
+            .map(|entry| (folder.join(&entry.name), entry.size.get_size(), true))
+            .collect::<Vec<_>>();
+
+        // We have to create new instance of Archive here
+        // because otherwise it may not work after get_entries method call
+        let mut archive = Archive::open(archive)?;
+
+        let options = *options;
+
+        std::thread::scope(|scope| {
+            // Temporary workaround as we can't get archive extraction process
+            // directly - we'll spawn it in another thread and check this archive entries appearence in the filesystem
+            let handle = scope.spawn(move || {
+                if options == ExtractOptions::default() {
+                    archive.extract(folder)
+                } else {
+                    archive.extract_by(folder, |_| true, &options)
+                }
+            });
+
+            let mut unpacked = 0;
+
+            loop {
+                let finished = handle.is_finished();
+
+                for (path, size, remained) in &mut entries {
+                    if *remained && path.exists() {
+                        *remained = false;
+
+                        unpacked += *size;
+                    }
+                }
+
+                (progress)(unpacked, total);
+
+                if finished {
+                    break;
+                }
+
+                std::thread::sleep(Duration::from_millis(250));
+            }
+
+            handle.join().unwrap_or_else(|err| std::panic::resume_unwind(err))
+        })
+    }
+}
@@ -1,11 +1,46 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
 
 use sysinfo::Disks;
 
-// TODO: support for relative paths
+/// Resolve absolute path with followed symlinks
+///
+/// Path doesn't need to exist: its longest existing parent will be resolved instead
+fn resolve(path: &Path) -> PathBuf {
+    let mut path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|current| current.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+
+    let mut rest = Vec::new();
+
+    loop {
+        if let Ok(resolved) = path.canonicalize() {
+            return rest.into_iter()
+                .rev()
+                .fold(resolved, |path, part| path.join(part));
+        }
+
+        match (path.file_name(), path.parent()) {
+            (Some(name), Some(parent)) => {
+                rest.push(name.to_os_string());
+
+                path = parent.to_path_buf();
+            }
+
+            _ => return path
+        }
+    }
+}
 
 /// Get available free disk space by specified path
 /// 
+/// Path can be relative and doesn't need to exist
+/// 
 /// Can return `None` if path is not prefixed by any available disk
 pub fn available(path: impl AsRef<Path>) -> Option<u64> {
     let mut disks = Disks::new();
@@ -20,9 +55,7 @@ pub fn available(path: impl AsRef<Path>) -> Option<u64> {
         a.cmp(&b).reverse()
     });
 
-    let path = path.as_ref()
-        .read_link()
-        .unwrap_or_else(|_| path.as_ref().to_path_buf());
+    let path = resolve(path.as_ref());
 
     for disk in disks.iter() {
         if path.starts_with(disk.mount_point()) {
@@ -47,13 +80,8 @@ pub fn is_same_disk(path1: impl AsRef<Path>, path2: impl AsRef<Path>) -> bool {
         a.cmp(&b).reverse()
     });
 
-    let path1 = path1.as_ref()
-        .read_link()
-        .unwrap_or_else(|_| path1.as_ref().to_path_buf());
-
-    let path2 = path2.as_ref()
-        .read_link()
-        .unwrap_or_else(|_| path2.as_ref().to_path_buf());
+    let path1 = resolve(path1.as_ref());
+    let path2 = resolve(path2.as_ref());
 
     for disk in disks.iter() {
         let disk_path = disk.mount_point();
@@ -65,3 +93,69 @@ pub fn is_same_disk(path1: impl AsRef<Path>, path2: impl AsRef<Path>) -> bool {
 
     false
 }
+
+/// Check if two paths are stored on the same filesystem
+///
+/// Unlike `is_same_disk` it compares device ids of the paths, so files can be renamed
+/// between them without copying. Paths don't need to exist: their longest existing parents are compared
+pub fn is_same_filesystem(path1: impl AsRef<Path>, path2: impl AsRef<Path>) -> bool {
+    fn device(path: &Path) -> Option<u64> {
+        resolve(path).ancestors()
+            .find_map(|path| path.metadata().ok())
+            .map(|metadata| metadata.dev())
+    }
+
+    match (device(path1.as_ref()), device(path2.as_ref())) {
+        (Some(device1), Some(device2)) => device1 == device2,
+
+        _ => false
+    }
+}
+
+/// Reserve disk space for `len` bytes of the file starting from `offset`
+///
+/// File size is not changed, so partially downloaded files can still be continued.
+/// Only supported on Linux, other systems return `ErrorKind::Unsupported`
+pub fn preallocate(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+
+        if len == 0 {
+            return Ok(());
+        }
+
+        // SAFETY: the file descriptor is owned by the file and stays open during the call
+        let result = unsafe {
+            libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, offset as libc::off_t, len as libc::off_t)
+        };
+
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (file, offset, len);
+
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "files preallocation is not supported on this system"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_same_filesystem() {
+        let temp = std::env::temp_dir();
+
+        assert!(is_same_filesystem(&temp, temp.join("missing/folder")));
+
+        // procfs is always mounted separately
+        assert!(!is_same_filesystem(&temp, "/proc"));
+    }
+}
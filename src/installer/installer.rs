@@ -1,14 +1,24 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::os::unix::prelude::PermissionsExt;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 use serde::{Serialize, Deserialize};
+use md5::{Md5, Digest};
 
 use super::downloader::{Downloader, DownloadingError};
-use super::archives::Archive;
+use super::archives::{self, Archive, ExtractOptions, OverwritePolicy};
+use super::extractor::{ArchiveExtractor, DefaultExtractor};
 use super::free_space;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Update {
+    /// Requesting size of the downloaded file before downloading it.
+    /// Can take a while for slow servers
+    ///
+    /// `(uri)`
+    CheckingSize(String),
+
     CheckingFreeSpace(PathBuf),
 
     /// `(temp path)`
@@ -35,7 +45,10 @@ pub enum Update {
     UnpackingProgress(u64, u64),
 
     UnpackingFinished,
-    UnpackingError(String)
+    UnpackingError(String),
+
+    /// Installation was stopped by the cancel flag
+    Cancelled
 }
 
 impl From<DownloadingError> for Update {
@@ -50,25 +63,86 @@ pub struct Installer {
     pub downloader: Downloader,
 
     /// Path to the temp folder used to store archive before unpacking
+    ///
+    /// Every archive is stored in its own subfolder named by the hash of its uri,
+    /// so concurrent installations can share the same temp folder
     pub temp_folder: PathBuf,
 
     /// Perform free space verifications before downloading file
     pub check_free_space: bool,
 
     /// How `Downloader` should save the file before unpacking it
-    pub filename: Option<String>
+    pub filename: Option<String>,
+
+    /// Extract archive while it's being downloaded without storing it in the temp folder
+    ///
+    /// Archives which can't be streamed are downloaded and unpacked as usual
+    pub stream_extract: bool,
+
+    /// Keep downloaded archive in the temp folder if it couldn't be unpacked
+    ///
+    /// Archives interrupted by network errors or the cancel flag
+    /// are always kept so their downloading can be continued
+    pub keep_temp_on_error: bool,
+
+    /// Set modification times of the unpacked files to the ones stored in the archive
+    ///
+    /// Tar and 7z archives always preserve them. Disabled by default
+    pub preserve_mtime: bool,
+
+    /// How already existing files are handled when the archive is unpacked
+    ///
+    /// Archives are not extracted while downloading unless it's `OverwritePolicy::AlwaysOverwrite`.
+    /// `OverwritePolicy::OverwriteIfNewer` compares modification times, so zip archives
+    /// should be installed with `preserve_mtime` for it to work. Default is `AlwaysOverwrite`
+    pub overwrite_policy: OverwritePolicy,
+
+    /// Lower memory usage of the unpacking at the cost of its speed
+    ///
+    /// See `ExtractOptions::low_memory` for details. Disabled by default
+    pub low_memory: bool,
+
+    /// Don't write archive entries identical to the already existing files,
+    /// e.g. when the archive is unpacked over a partial installation
+    ///
+    /// Existing files have to be hashed, so this can be slower if most of the files are changed.
+    /// Archives are not extracted while downloading if enabled. Disabled by default
+    ///
+    /// See `ExtractOptions::skip_identical` for details
+    pub skip_identical: bool,
+
+    /// Tool used to unpack downloaded archives
+    ///
+    /// Default is `DefaultExtractor` which chooses the way to unpack the archive
+    /// based on its detected format. Archives extracted while downloading don't use it
+    pub extractor: Arc<dyn ArchiveExtractor>
 }
 
 impl Installer {
     #[inline]
-    pub fn new<T: AsRef<str>>(uri: T) -> Result<Self, minreq::Error> {
+    pub fn new<T: AsRef<str>>(uri: T) -> Result<Self, DownloadingError> {
+        Self::new_with_proxy(uri, None)
+    }
+
+    #[inline]
+    /// Create installer which downloads the archive through the proxy
+    ///
+    /// See `Downloader::proxy` for supported proxies
+    pub fn new_with_proxy<T: AsRef<str>>(uri: T, proxy: Option<String>) -> Result<Self, DownloadingError> {
         Ok(Self {
-            downloader: Downloader::new(uri.as_ref())?
+            downloader: Downloader::new_with_proxy(uri.as_ref(), proxy)?
                 .with_free_space_check(false),
 
             temp_folder: std::env::temp_dir(),
             check_free_space: true,
-            filename: None
+            filename: None,
+            stream_extract: false,
+            keep_temp_on_error: false,
+            preserve_mtime: false,
+            overwrite_policy: OverwritePolicy::default(),
+            low_memory: false,
+            skip_identical: false,
+            extractor: Arc::new(DefaultExtractor)
         })
     }
 
@@ -81,9 +155,14 @@ impl Installer {
         self.filename.as_deref().unwrap_or(self.downloader.get_filename())
     }
 
-    #[inline]
-    fn get_temp_path(&self) -> PathBuf {
-        self.temp_folder.join(self.get_filename())
+    /// Get subfolder of the temp folder used to store the downloaded archive
+    ///
+    /// Its name depends only on the archive's uri, so downloading
+    /// can be continued by another installer of the same archive
+    pub fn get_temp_subfolder(&self) -> PathBuf {
+        let hash = format!("{:x}", Md5::digest(self.downloader.uri()));
+
+        self.temp_folder.join(format!(".anime-game-core-{}", &hash[..16]))
     }
 
     #[inline]
@@ -110,15 +189,175 @@ impl Installer {
         self
     }
 
+    #[inline]
+    /// Specify whether the archive should be extracted while it's being downloaded
+    pub fn with_stream_extract(mut self, stream_extract: bool) -> Self {
+        self.stream_extract = stream_extract;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether the archive should be extracted while it's being downloaded
+    pub fn set_stream_extract(&mut self, stream_extract: bool) {
+        self.stream_extract = stream_extract;
+    }
+
+    #[inline]
+    /// Specify whether downloaded archive should be kept in the temp folder if it couldn't be unpacked
+    pub fn with_keep_temp_on_error(mut self, keep_temp_on_error: bool) -> Self {
+        self.keep_temp_on_error = keep_temp_on_error;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether downloaded archive should be kept in the temp folder if it couldn't be unpacked
+    pub fn set_keep_temp_on_error(&mut self, keep_temp_on_error: bool) {
+        self.keep_temp_on_error = keep_temp_on_error;
+    }
+
+    #[inline]
+    /// Specify whether unpacked files should keep modification times stored in the archive
+    pub fn with_preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether unpacked files should keep modification times stored in the archive
+    pub fn set_preserve_mtime(&mut self, preserve_mtime: bool) {
+        self.preserve_mtime = preserve_mtime;
+    }
+
+    #[inline]
+    /// Specify how already existing files should be handled when the archive is unpacked
+    pub fn with_overwrite_policy(mut self, overwrite_policy: OverwritePolicy) -> Self {
+        self.overwrite_policy = overwrite_policy;
+
+        self
+    }
+
+    #[inline]
+    /// Specify how already existing files should be handled when the archive is unpacked
+    pub fn set_overwrite_policy(&mut self, overwrite_policy: OverwritePolicy) {
+        self.overwrite_policy = overwrite_policy;
+    }
+
+    #[inline]
+    /// Specify whether unpacking should use less memory at the cost of its speed
+    pub fn with_low_memory(mut self, low_memory: bool) -> Self {
+        self.low_memory = low_memory;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether unpacking should use less memory at the cost of its speed
+    pub fn set_low_memory(&mut self, low_memory: bool) {
+        self.low_memory = low_memory;
+    }
+
+    #[inline]
+    /// Specify whether archive entries identical to the existing files should be skipped
+    pub fn with_skip_identical(mut self, skip_identical: bool) -> Self {
+        self.skip_identical = skip_identical;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether archive entries identical to the existing files should be skipped
+    ///
+    /// See `Installer::skip_identical`
+    pub fn set_skip_identical(&mut self, skip_identical: bool) {
+        self.skip_identical = skip_identical;
+    }
+
+    #[inline]
+    /// Specify tool used to unpack downloaded archives
+    pub fn with_extractor(mut self, extractor: impl ArchiveExtractor + 'static) -> Self {
+        self.extractor = Arc::new(extractor);
+
+        self
+    }
+
+    #[inline]
+    /// Specify tool used to unpack downloaded archives
+    pub fn set_extractor(&mut self, extractor: impl ArchiveExtractor + 'static) {
+        self.extractor = Arc::new(extractor);
+    }
+
+    #[inline]
+    fn is_streamed(&self) -> bool {
+        self.stream_extract &&
+        !self.skip_identical &&
+        self.overwrite_policy == OverwritePolicy::AlwaysOverwrite &&
+        archives::is_streamable(self.get_filename())
+    }
+
+    #[inline]
+    /// Specify flag used to stop the installation process
+    ///
+    /// Downloading will be stopped between downloaded chunks, and
+    /// the archive won't be unpacked if the flag was set during downloading
+    pub fn with_cancel_flag(mut self, cancel_flag: Arc<AtomicBool>) -> Self {
+        self.downloader.cancel_flag = Some(cancel_flag);
+
+        self
+    }
+
+    #[inline]
+    /// Specify flag used to pause downloading
+    pub fn with_pause_flag(mut self, pause_flag: Arc<AtomicBool>) -> Self {
+        self.downloader.pause_flag = Some(pause_flag);
+
+        self
+    }
+
     /// Download archive from specified uri and unpack it
+    ///
+    /// If the uri points to the first part of a split archive (`.001`), then all its parts
+    /// are downloaded to the temp folder using their original names and extracted together
     pub fn install(&mut self, unpack_to: impl Into<PathBuf>, updater: impl Fn(Update) + Clone + Send + 'static) {
+        // Uri of the downloader can be changed by mirrors and archive parts
+        let temp_folder = self.get_temp_subfolder();
+
+        (updater)(Update::CheckingSize(self.downloader.uri().to_string()));
+
+        let parts = match self.downloader.split_parts() {
+            Ok(parts) => parts,
+
+            Err(err) => {
+                tracing::error!("Failed to find split archive parts: {err}");
+
+                (updater)(DownloadingError::from(err).into());
+
+                return;
+            }
+        };
+
         tracing::trace!("Checking free space availability");
 
-        let temp_path = self.get_temp_path();
+        // Split archive parts must keep their names to be extracted
+        let temp_path = if parts.len() > 1 {
+            temp_folder.join(self.downloader.get_filename())
+        } else {
+            temp_folder.join(self.get_filename())
+        };
+
         let unpack_to = unpack_to.into();
 
+        let length = parts.iter()
+            .map(|(_, length)| *length)
+            .sum::<Option<u64>>();
+
+        // Streamed archive is not stored in the temp folder
+        let streamed = self.is_streamed();
+
         // Perform free space verifications if needed
-        if self.check_free_space {
+        if self.check_free_space && !streamed {
             // Check available free space for archive itself
             (updater)(Update::CheckingFreeSpace(temp_path.clone()));
 
@@ -136,7 +375,7 @@ impl Installer {
                 return;
             };
 
-            if let Some(required) = self.downloader.length() {
+            if let Some(required) = length {
                 // We can possibly store downloaded archive + unpacked data on the same disk
                 let required = if free_space::is_same_disk(&temp_path, &unpack_to) {
                     (required as f64 * 2.5).ceil() as u64
@@ -156,7 +395,9 @@ impl Installer {
                     return;
                 }
             }
+        }
 
+        if self.check_free_space {
             // Check available free space for unpacked archvie data (archive size * 1.5)
             (updater)(Update::CheckingFreeSpace(unpack_to.clone()));
 
@@ -168,9 +409,9 @@ impl Installer {
                 return;
             };
 
-            if let Some(required) = self.downloader.length() {
+            if let Some(required) = length {
                 // We can possibly store downloaded archive + unpacked data on the same disk
-                let required = if free_space::is_same_disk(&unpack_to, &temp_path) {
+                let required = if !streamed && free_space::is_same_disk(&unpack_to, &temp_path) {
                     (required as f64 * 2.5).ceil() as u64
                 } else {
                     (required as f64 * 1.5).ceil() as u64
@@ -186,122 +427,363 @@ impl Installer {
             }
         }
 
+        if streamed && self.install_streamed(&unpack_to, &updater) {
+            return;
+        }
+
         tracing::trace!("Downloading archive");
 
+        (updater)(Update::DownloadingStarted(temp_path.clone()));
+
+        // Report total size before the first byte is downloaded
+        if let Some(length) = length {
+            (updater)(Update::DownloadingProgress(0, length));
+        }
+
+        let mut downloaded = 0;
+
         // Download archive
-        let download_progress_updater = updater.clone();
+        for (i, (uri, part_length)) in parts.iter().enumerate() {
+            let part_path = if i == 0 {
+                temp_path.clone()
+            } else {
+                if let Err(err) = self.downloader.set_uri(uri) {
+                    tracing::error!("Failed to request split archive part: {err}");
 
-        (updater)(Update::DownloadingStarted(temp_path.clone()));
+                    (updater)(DownloadingError::from(err).into());
 
-        if let Err(err) = self.downloader.download(&temp_path, move |curr, total| (download_progress_updater)(Update::DownloadingProgress(curr, total))) {
-            tracing::error!("Failed to download archive: {err}");
+                    return;
+                }
 
-            (updater)(Update::DownloadingError(err));
+                temp_folder.join(self.downloader.get_filename())
+            };
 
-            return;
+            let download_progress_updater = updater.clone();
+            let total = length.unwrap_or_default();
+
+            let result = self.downloader.download(&part_path, move |curr, part_total| {
+                (download_progress_updater)(Update::DownloadingProgress(downloaded + curr, total.max(downloaded + part_total)));
+            });
+
+            if let Err(err) = result {
+                if err == DownloadingError::Cancelled {
+                    (updater)(Update::Cancelled);
+
+                    return;
+                }
+
+                tracing::error!("Failed to download archive: {err}");
+
+                // Downloaded file is broken so it can't be continued
+                if matches!(err, DownloadingError::SizeMismatch(_, _, _)) {
+                    remove_temp(&part_path, self.keep_temp_on_error);
+                }
+
+                (updater)(Update::DownloadingError(err));
+
+                return;
+            }
+
+            downloaded += part_length.or(self.downloader.length()).unwrap_or_default();
         }
 
         (updater)(Update::DownloadingFinished);
 
-        match Archive::open(&temp_path) {
-            Ok(mut archive) => {
-                // Temporary workaround as we can't get archive extraction process
-                // directly - we'll spawn it in another thread and check this archive entries appearence in the filesystem
-                let mut total = 0;
+        if self.downloader.is_cancelled() {
+            (updater)(Update::Cancelled);
+
+            return;
+        }
+
+        let entries = match self.extractor.entries(&temp_path) {
+            Ok(entries) => entries,
+
+            Err(err) => {
+                tracing::error!("Downloaded archive is corrupted: {err}");
+
+                remove_temp_folder(&temp_folder, self.keep_temp_on_error);
 
-                let entries = archive
-                    .get_entries()
-                    .expect("Failed to get archive entries");
+                (updater)(Update::UnpackingError(err.to_string()));
 
-                let entries_number = entries.len() as u64;
+                return;
+            }
+        };
+
+        let entries_number = entries.len() as u64;
+
+        (updater)(Update::UpdatingPermissionsStarted(unpack_to.clone()));
 
-                (updater)(Update::UpdatingPermissionsStarted(unpack_to.clone()));
+        for (i, entry) in entries.iter().enumerate() {
+            let path = unpack_to.join(&entry.name);
+
+            // Failed to change permissions => likely patch-related file and was made by the sudo, so root
+            #[allow(unused_must_use)]
+            if let Err(_) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o666)) {
+                // For weird reason we can delete files made by root, but can't modify their permissions
+                // We're not checking its result because if it's error - then it's either couldn't be removed (which is not the case)
+                // or the file doesn't exist, which we obviously can just ignore
+                std::fs::remove_file(&path);
+            }
+
+            (updater)(Update::UpdatingPermissions(i as u64 + 1, entries_number));
+        }
 
-                for (i, entry) in entries.iter().enumerate() {
-                    total += entry.size.get_size();
+        (updater)(Update::UpdatingPermissionsFinished);
 
-                    let path = unpack_to.join(&entry.name);
+        tracing::trace!("Extracting archive");
 
-                    // Failed to change permissions => likely patch-related file and was made by the sudo, so root
-                    #[allow(unused_must_use)]
-                    if let Err(_) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o666)) {
-                        // For weird reason we can delete files made by root, but can't modify their permissions
-                        // We're not checking its result because if it's error - then it's either couldn't be removed (which is not the case)
-                        // or the file doesn't exist, which we obviously can just ignore
-                        std::fs::remove_file(&path);
+        let extract_options = ExtractOptions {
+            overwrite_policy: self.overwrite_policy,
+            low_memory: self.low_memory,
+            skip_identical: self.skip_identical
+        };
+
+        (updater)(Update::UnpackingStarted(unpack_to.clone()));
+
+        let result = self.extractor.extract(&temp_path, &unpack_to, &extract_options, &|curr, total| {
+            (updater)(Update::UnpackingProgress(curr, total));
+        });
+
+        match result {
+            Ok(_) => {
+                if self.preserve_mtime {
+                    let restored = Archive::open(&temp_path)
+                        .and_then(|mut archive| archive.restore_mtimes(&unpack_to, |_| true));
+
+                    if let Err(err) = restored {
+                        tracing::warn!("Failed to restore modification times of the unpacked files: {err}");
                     }
+                }
+
+                remove_temp_folder(&temp_folder, false);
+
+                (updater)(Update::UnpackingFinished);
+            }
+
+            Err(err) => {
+                remove_temp_folder(&temp_folder, self.keep_temp_on_error);
+
+                (updater)(Update::UnpackingError(err.to_string()));
+            }
+        }
+    }
+
+    /// Extract archive while it's being downloaded
+    ///
+    /// Return `false` if the archive can't be streamed and should be downloaded first
+    fn install_streamed(&self, unpack_to: &Path, updater: &(impl Fn(Update) + Clone + Send + 'static)) -> bool {
+        tracing::trace!("Extracting archive while downloading it");
+
+        let download_progress_updater = updater.clone();
+
+        (updater)(Update::DownloadingStarted(unpack_to.to_path_buf()));
+
+        let stream = self.downloader.stream(move |curr, total| (download_progress_updater)(Update::DownloadingProgress(curr, total)));
+
+        let stream = match stream {
+            Ok(stream) => stream,
+
+            Err(err) => {
+                tracing::error!("Failed to download archive: {err}");
 
-                    (updater)(Update::UpdatingPermissions(i as u64 + 1, entries_number));
+                (updater)(Update::DownloadingError(err));
+
+                return true;
+            }
+        };
+
+        (updater)(Update::UnpackingStarted(unpack_to.to_path_buf()));
+
+        match archives::extract_stream(stream, self.get_filename(), unpack_to) {
+            Ok(Some(_)) => {
+                (updater)(Update::DownloadingFinished);
+                (updater)(Update::UnpackingFinished);
+            }
+
+            Ok(None) => {
+                tracing::warn!("Archive can't be extracted while downloading. Downloading it first");
+
+                return false;
+            }
+
+            Err(_) if self.downloader.is_cancelled() => (updater)(Update::Cancelled),
+
+            Err(err) => (updater)(Update::UnpackingError(err.to_string()))
+        }
+
+        true
+    }
+}
+
+/// Remove downloaded archive from the temp folder if it's not asked to be kept
+pub(crate) fn remove_temp(path: &Path, keep: bool) {
+    if !keep && path.exists() {
+        tracing::debug!("Removing temp file {:?}", path);
+
+        if let Err(err) = std::fs::remove_file(path) {
+            tracing::warn!("Failed to remove temp file {:?}: {err}", path);
+        }
+    }
+}
+
+/// Remove temp subfolder with all the downloaded archive parts if they're not asked to be kept
+fn remove_temp_folder(path: &Path, keep: bool) {
+    if !keep && path.exists() {
+        tracing::debug!("Removing temp folder {:?}", path);
+
+        if let Err(err) = std::fs::remove_dir_all(path) {
+            tracing::warn!("Failed to remove temp folder {:?}: {err}", path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::installer::downloader::tests::{serve, temp_file};
+
+    #[test]
+    fn test_temp_cleanup_on_error() {
+        let uri = serve("200 OK", &[("content-length", "5")], b"12345");
+        let temp_folder = temp_file("temp-cleanup");
+        let unpack_to = temp_file("temp-cleanup-unpacked");
+
+        std::fs::create_dir_all(&temp_folder).unwrap();
+
+        let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let updater_errors = errors.clone();
+
+        Installer::new(&uri).unwrap()
+            .with_temp_folder(&temp_folder)
+            .with_free_space_check(false)
+            .install(&unpack_to, move |update| {
+                if let Update::UnpackingError(err) = update {
+                    updater_errors.lock().unwrap().push(err);
                 }
+            });
 
-                (updater)(Update::UpdatingPermissionsFinished);
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        assert_eq!(std::fs::read_dir(&temp_folder).unwrap().count(), 0);
 
-                tracing::trace!("Extracting archive");
+        // Broken archive is kept for debugging if asked
+        Installer::new(&uri).unwrap()
+            .with_temp_folder(&temp_folder)
+            .with_free_space_check(false)
+            .with_keep_temp_on_error(true)
+            .install(&unpack_to, |_| {});
 
-                let unpacking_path = unpack_to.clone();
-                let unpacking_updater = updater.clone();
+        let installer = Installer::new(&uri).unwrap()
+            .with_temp_folder(&temp_folder);
 
-                let handle_2 = std::thread::spawn(move || {
-                    let mut entries = entries.into_iter()
-                        .map(|entry| (unpacking_path.join(&entry.name), entry.size.get_size(), true))
-                        .collect::<Vec<_>>();
+        assert!(installer.get_temp_subfolder().join("file.zip").exists());
 
-                    let mut unpacked = 0;
+        std::fs::remove_dir_all(temp_folder).unwrap();
+    }
 
-                    loop {
-                        std::thread::sleep(std::time::Duration::from_millis(250));
+    #[test]
+    fn test_shared_temp_folder() {
+        let archive = |content: &str| {
+            let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
 
-                        let mut empty = true;
+            archive.start_file("file.txt", zip::write::SimpleFileOptions::default()).unwrap();
 
-                        for (path, size, remained) in &mut entries {
-                            if *remained {
-                                empty = false;
+            std::io::Write::write_all(&mut archive, content.as_bytes()).unwrap();
 
-                                if std::path::Path::new(path).exists() {
-                                    *remained = false;
+            let archive = archive.finish().unwrap().into_inner();
+            let length = String::leak(archive.len().to_string());
 
-                                    unpacked += *size;
-                                }
-                            }
-                        }
+            // Both archives are served as `file.zip`
+            serve("200 OK", Vec::leak(vec![("content-length", &*length)]), Vec::leak(archive))
+        };
 
-                        (unpacking_updater)(Update::UnpackingProgress(unpacked, total));
+        let temp_folder = temp_file("shared-temp");
 
-                        if empty {
-                            break;
-                        }
-                    }
-                });
-
-                // Run archive extraction in another thread to not to freeze the current one
-                let handle_1 = std::thread::spawn(move || {
-                    (updater)(Update::UnpackingStarted(unpack_to.clone()));
-
-                    // We have to create new instance of Archive here
-                    // because otherwise it may not work after get_entries method call
-                    match Archive::open(&temp_path) {
-                        Ok(mut archive) => match archive.extract(unpack_to) {
-                            Ok(_) => {
-                                // TODO error handling
-                                #[allow(unused_must_use)] {
-                                    std::fs::remove_file(temp_path);
-                                }
-
-                                (updater)(Update::UnpackingFinished);
-                            }
-
-                            Err(err) => (updater)(Update::UnpackingError(err.to_string()))
+        std::fs::create_dir_all(&temp_folder).unwrap();
+
+        let handles = ["first", "second"].map(|content| {
+            let uri = archive(content);
+            let temp_folder = temp_folder.clone();
+            let unpack_to = temp_file(&format!("shared-temp-{content}"));
+
+            std::thread::spawn(move || {
+                let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+                let updater_errors = errors.clone();
+
+                Installer::new(uri).unwrap()
+                    .with_temp_folder(temp_folder)
+                    .with_free_space_check(false)
+                    .install(&unpack_to, move |update| {
+                        if let Update::UnpackingError(err) | Update::DownloadingError(DownloadingError::Minreq(err)) = update {
+                            updater_errors.lock().unwrap().push(err);
                         }
+                    });
 
-                        Err(err) => (updater)(Update::UnpackingError(err.to_string()))
-                    }
-                });
+                assert!(errors.lock().unwrap().is_empty());
+                assert_eq!(std::fs::read_to_string(unpack_to.join("file.txt")).unwrap(), content);
+
+                std::fs::remove_dir_all(unpack_to).unwrap();
+            })
+        });
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // Temp subfolders are removed after installation
+        assert_eq!(std::fs::read_dir(&temp_folder).unwrap().count(), 0);
 
-                handle_1.join().unwrap();
-                handle_2.join().unwrap();
+        std::fs::remove_dir_all(temp_folder).unwrap();
+    }
+
+    #[test]
+    fn test_custom_extractor() {
+        #[derive(Debug)]
+        struct MockExtractor;
+
+        impl ArchiveExtractor for MockExtractor {
+            fn entries(&self, _archive: &Path) -> anyhow::Result<Vec<archives::Entry>> {
+                Ok(vec![archives::Entry {
+                    name: String::from("file.txt"),
+                    size: archives::Size::Uncompressed(5)
+                }])
             }
 
-            Err(err) => (updater)(Update::UnpackingError(err.to_string()))
+            fn extract(&self, archive: &Path, folder: &Path, _options: &ExtractOptions, progress: &dyn Fn(u64, u64)) -> anyhow::Result<()> {
+                std::fs::create_dir_all(folder)?;
+                std::fs::copy(archive, folder.join("file.txt"))?;
+
+                (progress)(5, 5);
+
+                Ok(())
+            }
         }
+
+        // Not an archive, but the mock doesn't care
+        let uri = serve("200 OK", &[("content-length", "5")], b"12345");
+        let temp_folder = temp_file("custom-extractor");
+        let unpack_to = temp_file("custom-extractor-unpacked");
+
+        std::fs::create_dir_all(&temp_folder).unwrap();
+
+        let updates = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let installer_updates = updates.clone();
+
+        Installer::new(&uri).unwrap()
+            .with_temp_folder(&temp_folder)
+            .with_free_space_check(false)
+            .with_extractor(MockExtractor)
+            .install(&unpack_to, move |update| installer_updates.lock().unwrap().push(update));
+
+        let updates = updates.lock().unwrap();
+
+        assert!(updates.contains(&Update::UnpackingProgress(5, 5)));
+        assert_eq!(updates.last(), Some(&Update::UnpackingFinished));
+
+        assert_eq!(std::fs::read_to_string(unpack_to.join("file.txt")).unwrap(), "12345");
+        assert_eq!(std::fs::read_dir(&temp_folder).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(temp_folder).unwrap();
+        std::fs::remove_dir_all(unpack_to).unwrap();
     }
 }
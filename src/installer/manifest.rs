@@ -0,0 +1,418 @@
+use std::path::{Path, PathBuf, Component};
+
+use serde::{Serialize, Deserialize};
+
+use crate::repairer::IntegrityFile;
+
+/// Name of the file with list of files which should be patched after installation
+pub const HDIFF_FILES: &str = "hdifffiles.txt";
+
+/// Name of the file with list of outdated files which should be removed after installation
+pub const DELETE_FILES: &str = "deletefiles.txt";
+
+/// Entry of the `hdifffiles.txt` file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HdiffFile {
+    /// Path to the patched file relative to the game folder
+    #[serde(rename = "remoteName")]
+    pub remote_name: String,
+
+    /// Expected MD5 hash of the file before patching
+    #[serde(rename = "sourceMd5", default)]
+    pub source_md5: Option<String>
+}
+
+/// Parse content of the `hdifffiles.txt` file
+///
+/// Every entry is a JSON object with the `remoteName` field storing path to the patched file
+/// relative to the game folder. Entries which can't be parsed are skipped
+///
+/// ```text
+/// {"remoteName": "AnimeGame_Data/StreamingAssets/Audio/GeneratedSoundBanks/Windows/Japanese/1001.pck"}
+/// ```
+pub fn parse_hdiff_files(content: impl AsRef<str>) -> Vec<String> {
+    parse_hdiff_entries(content).into_iter()
+        .map(|file| file.remote_name)
+        .collect()
+}
+
+/// Parse content of the `hdifffiles.txt` file keeping optional
+/// `sourceMd5` hashes of the files before patching
+///
+/// See `parse_hdiff_files`
+pub fn parse_hdiff_entries(content: impl AsRef<str>) -> Vec<HdiffFile> {
+    let mut content = content.as_ref();
+    let mut files = Vec::new();
+
+    while !content.trim().is_empty() {
+        let mut entries = serde_json::Deserializer::from_str(content)
+            .into_iter::<HdiffFile>();
+
+        match entries.next() {
+            Some(Ok(file)) => {
+                files.push(file);
+
+                content = &content[entries.byte_offset()..];
+            }
+
+            // Skip the whole line if it can't be parsed
+            Some(Err(err)) => {
+                let (line, rest) = content.trim_start()
+                    .split_once('\n')
+                    .unwrap_or((content, ""));
+
+                tracing::warn!("Failed to parse hdiff file entry {line:?}: {err}");
+
+                content = rest;
+            }
+
+            None => break
+        }
+    }
+
+    files
+}
+
+/// Read list of files which should be patched from the `hdifffiles.txt` stored in the game folder
+///
+/// Returned values are relative to the game folder
+pub fn read_hdiff_files(game_path: impl AsRef<Path>) -> std::io::Result<Vec<String>> {
+    Ok(parse_hdiff_files(std::fs::read_to_string(game_path.as_ref().join(HDIFF_FILES))?))
+}
+
+/// Read entries of the `hdifffiles.txt` stored in the game folder
+///
+/// See `parse_hdiff_entries`
+pub fn read_hdiff_entries(game_path: impl AsRef<Path>) -> std::io::Result<Vec<HdiffFile>> {
+    Ok(parse_hdiff_entries(std::fs::read_to_string(game_path.as_ref().join(HDIFF_FILES))?))
+}
+
+/// State of the file listed in the `hdifffiles.txt`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HdiffState {
+    /// Patch is not applied yet
+    Pending,
+
+    /// Original file was removed but the patched one wasn't moved in its place.
+    /// Happens when installation process was interrupted while applying the patch
+    Interrupted,
+
+    /// Patch was already applied, e.g. by the previous installation attempt
+    Applied
+}
+
+/// Get state of the file listed in the `hdifffiles.txt`
+///
+/// Patch is considered applied if `<file>.hdiff` doesn't exist anymore,
+/// and interrupted if only `<file>.hdiff_patched` remained
+pub fn hdiff_state(game_path: impl AsRef<Path>, relative_file: impl AsRef<str>) -> HdiffState {
+    let game_path = game_path.as_ref();
+    let relative_file = relative_file.as_ref();
+
+    let file = game_path.join(relative_file);
+    let patch = game_path.join(format!("{relative_file}.hdiff"));
+    let output = game_path.join(format!("{relative_file}.hdiff_patched"));
+
+    if !file.exists() && output.exists() {
+        HdiffState::Interrupted
+    }
+
+    else if !patch.exists() {
+        HdiffState::Applied
+    }
+
+    else {
+        HdiffState::Pending
+    }
+}
+
+/// Split files listed in the `hdifffiles.txt` into the ones which still need to be patched
+/// and the ones which already match their hashes from the integrity files (e.g. the `pkg_version`)
+///
+/// Files which are not listed in the integrity files are considered not patched
+///
+/// Return `(not patched, already patched)` lists
+pub fn split_verified_hdiff_files(game_path: impl AsRef<Path>, files: Vec<String>, integrity: &[IntegrityFile]) -> (Vec<String>, Vec<String>) {
+    let game_path = game_path.as_ref();
+
+    files.into_iter().partition(|file| {
+        let verified = integrity.iter()
+            .find(|integrity| integrity.path == Path::new(file))
+            .map(|integrity| integrity.verify(game_path))
+            .unwrap_or(false);
+
+        !verified
+    })
+}
+
+/// Read list of outdated files from the `deletefiles.txt` stored in the game folder
+///
+/// Listed paths are relative to the game folder, so returned values are joined with it.
+/// Surrounding whitespaces and carriage returns (e.g. from CRLF line endings) are trimmed
+///
+/// ```text
+/// AnimeGame_Data/Plugins/metakeeper.dll
+/// ```
+pub fn read_delete_files(game_path: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    let game_path = game_path.as_ref();
+
+    let files = std::fs::read_to_string(game_path.join(DELETE_FILES))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| game_path.join(line))
+        .collect();
+
+    Ok(files)
+}
+
+/// Sum sizes of the listed files which currently exist on disk
+///
+/// Can be used with `read_delete_files` to estimate how much space will be freed
+pub fn existing_files_size(files: &[PathBuf]) -> u64 {
+    files.iter()
+        .flat_map(|file| file.metadata())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Remove outdated file listed in the `deletefiles.txt`
+///
+/// Return `false` if the file doesn't exist, e.g. because it was removed
+/// manually or by the previous installation attempt
+pub fn remove_outdated_file(file: impl AsRef<Path>) -> std::io::Result<bool> {
+    let file = file.as_ref();
+
+    if file.symlink_metadata().is_err() {
+        return Ok(false);
+    }
+
+    std::fs::remove_file(file)?;
+
+    Ok(true)
+}
+
+/// Check that the path is located inside of the game folder
+///
+/// Path must be relative to the game folder (or already joined with it) and must not
+/// contain `..` components. If some of its parent folders are symlinks then
+/// they must point inside of the game folder as well
+pub fn is_safe_path(game_path: impl AsRef<Path>, path: impl AsRef<Path>) -> bool {
+    let game_path = game_path.as_ref();
+    let path = path.as_ref();
+
+    let Ok(root) = game_path.canonicalize() else {
+        return false;
+    };
+
+    let relative = path.strip_prefix(game_path).unwrap_or(path);
+
+    if relative.is_absolute() {
+        return false;
+    }
+
+    let is_normal = relative.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+    if !is_normal {
+        return false;
+    }
+
+    // The file itself can be a symlink but removing or replacing it
+    // won't touch the file it points to, so we check only parent folders
+    let mut parent = game_path.join(relative);
+
+    while parent.pop() {
+        if parent.exists() {
+            return match parent.canonicalize() {
+                Ok(parent) => parent.starts_with(&root),
+                Err(_) => false
+            };
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_game_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(".anime-game-core-test-{name}-{}", std::process::id()));
+
+        if path.exists() {
+            std::fs::remove_dir_all(&path).unwrap();
+        }
+
+        std::fs::create_dir_all(&path).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn test_delete_files_relative_to_game_dir() {
+        let game_dir = temp_game_dir("delete-files");
+
+        std::fs::create_dir_all(game_dir.join("AnimeGame_Data/Plugins")).unwrap();
+        std::fs::write(game_dir.join("AnimeGame_Data/Plugins/metakeeper.dll"), "dll").unwrap();
+        std::fs::write(game_dir.join(DELETE_FILES), "AnimeGame_Data/Plugins/metakeeper.dll\n").unwrap();
+
+        let files = read_delete_files(&game_dir).unwrap();
+
+        assert_eq!(files, vec![game_dir.join("AnimeGame_Data/Plugins/metakeeper.dll")]);
+        assert_eq!(existing_files_size(&files), 3);
+
+        for file in &files {
+            assert!(remove_outdated_file(file).unwrap());
+        }
+
+        assert!(!game_dir.join("AnimeGame_Data/Plugins/metakeeper.dll").exists());
+
+        // Already removed files are skipped
+        assert!(!remove_outdated_file(&files[0]).unwrap());
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+
+    #[test]
+    fn test_crlf_manifests() {
+        let game_dir = temp_game_dir("crlf-manifests");
+
+        std::fs::write(game_dir.join(DELETE_FILES), "AnimeGame_Data/level0\r\nAnimeGame_Data/level1 \r\n\r\nAnimeGame_Data/level2\r").unwrap();
+
+        assert_eq!(read_delete_files(&game_dir).unwrap(), vec![
+            game_dir.join("AnimeGame_Data/level0"),
+            game_dir.join("AnimeGame_Data/level1"),
+            game_dir.join("AnimeGame_Data/level2")
+        ]);
+
+        let files = parse_hdiff_files("{\"remoteName\": \"AnimeGame_Data/level0\"}\r\ngarbage\r\n{\"remoteName\": \"AnimeGame_Data/level1\"}\r\n");
+
+        assert_eq!(files, vec!["AnimeGame_Data/level0", "AnimeGame_Data/level1"]);
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_hdiff_files() {
+        let files = parse_hdiff_files(concat!(
+            "{\"remoteName\": \"AnimeGame_Data/level0\"}\n",
+            "{ \"remoteName\" :\"AnimeGame_Data/level1\", \"md5\": \"8c8c3d845b957e4cb84c662bed44d072\", \"fileSize\": 33466104 }\n",
+            "{\"fileSize\": 10, \"remoteName\": \"AnimeGame_Data/level2\"}\n",
+            "{\n",
+            "    \"remoteName\": \"AnimeGame_Data/level3\"\n",
+            "}\n",
+            "garbage\n",
+            "{\"localName\": \"AnimeGame_Data/level4\"}\n",
+            "\n",
+            "{\"remoteName\": \"AnimeGame_Data/level5\"}"
+        ));
+
+        assert_eq!(files, vec![
+            "AnimeGame_Data/level0",
+            "AnimeGame_Data/level1",
+            "AnimeGame_Data/level2",
+            "AnimeGame_Data/level3",
+            "AnimeGame_Data/level5"
+        ]);
+    }
+
+    #[test]
+    fn test_parse_hdiff_source_md5() {
+        let files = parse_hdiff_entries(concat!(
+            "{\"remoteName\": \"AnimeGame_Data/level0\", \"sourceMd5\": \"8c8c3d845b957e4cb84c662bed44d072\"}\n",
+            "{\"remoteName\": \"AnimeGame_Data/level1\"}"
+        ));
+
+        assert_eq!(files, vec![
+            HdiffFile {
+                remote_name: String::from("AnimeGame_Data/level0"),
+                source_md5: Some(String::from("8c8c3d845b957e4cb84c662bed44d072"))
+            },
+            HdiffFile {
+                remote_name: String::from("AnimeGame_Data/level1"),
+                source_md5: None
+            }
+        ]);
+    }
+
+    #[test]
+    fn test_hdiff_state_after_partial_run() {
+        let game_dir = temp_game_dir("hdiff-state");
+
+        // Not patched yet
+        std::fs::write(game_dir.join("level0"), "").unwrap();
+        std::fs::write(game_dir.join("level0.hdiff"), "").unwrap();
+
+        // Interrupted after the original file was removed
+        std::fs::write(game_dir.join("level1.hdiff"), "").unwrap();
+        std::fs::write(game_dir.join("level1.hdiff_patched"), "").unwrap();
+
+        // Interrupted after the patched file was moved in place of the original one
+        std::fs::write(game_dir.join("level2"), "").unwrap();
+
+        assert_eq!(hdiff_state(&game_dir, "level0"), HdiffState::Pending);
+        assert_eq!(hdiff_state(&game_dir, "level1"), HdiffState::Interrupted);
+        assert_eq!(hdiff_state(&game_dir, "level2"), HdiffState::Applied);
+
+        // Both original file and the patch are gone
+        assert_eq!(hdiff_state(&game_dir, "level3"), HdiffState::Applied);
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+
+    #[test]
+    fn test_split_verified_hdiff_files() {
+        let game_dir = temp_game_dir("verified-hdiff");
+
+        // Already patched file
+        std::fs::write(game_dir.join("level0"), "patched").unwrap();
+
+        // Not patched yet
+        std::fs::write(game_dir.join("level1"), "original").unwrap();
+
+        let integrity = ["level0", "level1"].map(|name| IntegrityFile {
+            path: PathBuf::from(name),
+            md5: String::from("550069b20a946bc1530db3ce5b4d0474"), // md5("patched")
+            size: 7,
+            base_url: String::new()
+        });
+
+        let files = vec![
+            String::from("level0"),
+            String::from("level1"),
+            String::from("level2")
+        ];
+
+        let (pending, verified) = split_verified_hdiff_files(&game_dir, files, &integrity);
+
+        assert_eq!(pending, vec!["level1", "level2"]);
+        assert_eq!(verified, vec!["level0"]);
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+
+    #[test]
+    fn test_unsafe_paths() {
+        let game_dir = temp_game_dir("unsafe-paths");
+        let outside_dir = temp_game_dir("unsafe-paths-outside");
+
+        std::fs::create_dir_all(game_dir.join("AnimeGame_Data")).unwrap();
+        std::os::unix::fs::symlink(&outside_dir, game_dir.join("escape")).unwrap();
+
+        assert!(is_safe_path(&game_dir, "AnimeGame_Data/level0"));
+        assert!(is_safe_path(&game_dir, game_dir.join("AnimeGame_Data/level0")));
+        assert!(is_safe_path(&game_dir, "not/yet/created/file"));
+
+        assert!(!is_safe_path(&game_dir, "../something"));
+        assert!(!is_safe_path(&game_dir, "AnimeGame_Data/../../something"));
+        assert!(!is_safe_path(&game_dir, game_dir.join("../something")));
+        assert!(!is_safe_path(&game_dir, "/etc/passwd"));
+        assert!(!is_safe_path(&game_dir, "escape/something"));
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+        std::fs::remove_dir_all(outside_dir).unwrap();
+    }
+}
@@ -2,6 +2,14 @@ pub mod downloader;
 pub mod archives;
 pub mod installer;
 pub mod free_space;
+pub mod options;
+pub mod manifest;
+pub mod report;
+pub mod plan;
+pub mod patcher;
+pub mod extractor;
+pub mod transaction;
+pub mod diff;
 
 pub mod prelude {
     pub use super::archives::Archive;
@@ -9,11 +17,21 @@ pub mod prelude {
 
     pub use super::downloader::{
         Downloader,
-        DownloadingError
+        DownloadingError,
+        DownloadProgress,
+        DownloadEvent,
+        Auth
     };
 
     pub use super::installer::{
         Installer,
         Update as InstallerUpdate
     };
+
+    pub use super::options::InstallOptions;
+    pub use super::report::{InstallReport, FreeSpaceUsage};
+    pub use super::plan::InstallPlan;
+    pub use super::patcher::{HdiffPatcher, HdiffPatchError, Hpatchz, PatchDecision};
+    pub use super::extractor::{ArchiveExtractor, DefaultExtractor};
+    pub use super::transaction::Transaction;
 }
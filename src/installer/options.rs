@@ -0,0 +1,435 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::downloader::{Downloader, DownloadingError, Auth, DEFAULT_CHUNK_SIZE};
+use super::patcher::{HdiffPatcher, Hpatchz};
+use super::manifest;
+
+/// Options used by the `install_to_by`-like methods of the version differences
+#[derive(Debug, Clone)]
+pub struct InstallOptions {
+    /// Flag used to stop the installation process
+    ///
+    /// When set to `true`, installation will be stopped as soon as possible
+    /// leaving already processed files in place, so it can be continued later
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+
+    /// Flag used to pause downloading
+    ///
+    /// While it's set to `true`, downloading is blocked
+    /// without closing the connection
+    pub pause_flag: Option<Arc<AtomicBool>>,
+
+    /// Amount of threads used to apply hdiff patches
+    ///
+    /// Default is amount of available CPU cores
+    pub hdiff_workers: usize,
+
+    /// Skip hdiff patches for files which already match their hashes
+    /// from the `pkg_version` manifest, e.g. patched by an interrupted installation
+    pub skip_verified: bool,
+
+    /// Continue installation if some hdiff patches can't be applied
+    ///
+    /// Failed files are listed in the `InstallReport::failed_patches`
+    /// and can be fixed later by the files repairer. Disabled by default
+    pub continue_on_patch_error: bool,
+
+    /// Maximal downloading speed in bytes per second
+    ///
+    /// `None` means no limit
+    pub speed_limit: Option<u64>,
+
+    /// Proxy used to download files
+    ///
+    /// See `Downloader::proxy` for supported proxies
+    pub proxy: Option<String>,
+
+    /// Value of the `User-Agent` header used to download files
+    ///
+    /// Default is `downloader::DEFAULT_USER_AGENT`
+    pub user_agent: Option<String>,
+
+    /// Additional headers sent with downloading requests
+    pub headers: Vec<(String, String)>,
+
+    /// Credentials sent with downloading requests
+    pub auth: Option<Auth>,
+
+    /// Amount of connections used to download every file
+    ///
+    /// See `Downloader::connections`
+    pub connections: u8,
+
+    /// Amount of bytes buffered in memory before they're written to the downloaded file
+    ///
+    /// See `Downloader::chunk_size`. Default is `downloader::DEFAULT_CHUNK_SIZE`
+    pub chunk_size: usize,
+
+    /// Extract archive while it's being downloaded without storing it in the temp folder
+    ///
+    /// Used only for single-segment zip and tar archives. Other ones are downloaded and unpacked as usual.
+    /// Archives with known checksums are downloaded first as well, because they must be verified
+    /// before anything is extracted to the installation folder
+    pub stream_extract: bool,
+
+    /// Temp folder used to store downloaded archives instead of the version difference's one
+    pub temp_folder: Option<PathBuf>,
+
+    /// Use the installation folder or the system temp folder if the temp folder
+    /// is read-only or doesn't have enough free space
+    ///
+    /// Otherwise installation fails with `DiffDownloadingError::TempUnavailable`
+    /// or `DownloadingError::NoSpaceAvailable`. Disabled by default
+    pub temp_fallback: bool,
+
+    /// Use the installation folder to store downloaded archives if the temp folder
+    /// is stored on another filesystem
+    ///
+    /// Files can't be moved between filesystems without copying them, so this
+    /// keeps all the installation files on the same one. The temp folder is used
+    /// if the installation folder can't store the archives. Disabled by default
+    ///
+    /// `DiffUpdate::TempOnAnotherFilesystem` is sent in both cases
+    pub relocate_temp: bool,
+
+    /// Keep downloaded archives in the temp folder if they couldn't be verified or unpacked
+    ///
+    /// Archives interrupted by network errors or the cancel flag
+    /// are always kept so their downloading can be continued
+    pub keep_temp_on_error: bool,
+
+    /// Set modification times of the unpacked files to the ones stored in the archive
+    ///
+    /// Files updated by hdiff patches get modification times of their patches.
+    /// Tar and 7z archives always preserve them. Disabled by default
+    pub preserve_mtime: bool,
+
+    /// Lower memory usage of archives unpacking at the cost of its speed
+    ///
+    /// See `ExtractOptions::low_memory` for details. Disabled by default
+    pub low_memory: bool,
+
+    /// Don't write archive entries identical to the already existing files,
+    /// e.g. when a full archive is installed over a partial installation
+    ///
+    /// Existing files have to be hashed, so this can be slower if most of them are changed.
+    /// Archives are not extracted while downloading if enabled. Disabled by default
+    ///
+    /// See `ExtractOptions::skip_identical` for details
+    pub skip_identical: bool,
+
+    /// Remove outdated files listed in the `deletefiles.txt`
+    ///
+    /// Skipping this step keeps e.g. modded files in place, but may also leave
+    /// stale files the game doesn't use anymore. Default is `true`
+    pub apply_deletefiles: bool,
+
+    /// Restore all the changed files if installation fails or is cancelled
+    ///
+    /// Files are copied to the temp folder before they're changed, so this mode
+    /// requires additional disk space. Archives are not extracted while downloading
+    pub transactional: bool,
+
+    /// Install only files which paths start with one of these prefixes
+    ///
+    /// Other archive entries are not extracted, and their hdiff patches and outdated
+    /// files removal are skipped. Installation manifests are always extracted.
+    /// Archives are not extracted while downloading. `None` means all the files
+    pub extract_prefixes: Option<Vec<String>>,
+
+    /// Tool used to apply hdiff patches
+    ///
+    /// Default is `Hpatchz` which runs the `hpatchz` binary
+    pub patcher: Arc<dyn HdiffPatcher>
+}
+
+impl Default for InstallOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            cancel_flag: None,
+            pause_flag: None,
+            hdiff_workers: std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+            skip_verified: false,
+            continue_on_patch_error: false,
+            speed_limit: None,
+            proxy: None,
+            user_agent: None,
+            headers: Vec::new(),
+            auth: None,
+            connections: 1,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            stream_extract: false,
+            temp_folder: None,
+            temp_fallback: false,
+            relocate_temp: false,
+            keep_temp_on_error: false,
+            preserve_mtime: false,
+            low_memory: false,
+            skip_identical: false,
+            apply_deletefiles: true,
+            transactional: false,
+            extract_prefixes: None,
+            patcher: Arc::new(Hpatchz)
+        }
+    }
+}
+
+impl InstallOptions {
+    #[inline]
+    /// Specify flag used to stop the installation process
+    pub fn with_cancel_flag(mut self, cancel_flag: Arc<AtomicBool>) -> Self {
+        self.cancel_flag = Some(cancel_flag);
+
+        self
+    }
+
+    #[inline]
+    /// Specify flag used to pause downloading
+    pub fn with_pause_flag(mut self, pause_flag: Arc<AtomicBool>) -> Self {
+        self.pause_flag = Some(pause_flag);
+
+        self
+    }
+
+    #[inline]
+    /// Specify amount of threads used to apply hdiff patches
+    pub fn with_hdiff_workers(mut self, hdiff_workers: usize) -> Self {
+        self.hdiff_workers = hdiff_workers;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether hdiff patches should be skipped for files which are already patched
+    pub fn with_skip_verified(mut self, skip_verified: bool) -> Self {
+        self.skip_verified = skip_verified;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether installation should continue if some hdiff patches can't be applied
+    pub fn with_continue_on_patch_error(mut self, continue_on_patch_error: bool) -> Self {
+        self.continue_on_patch_error = continue_on_patch_error;
+
+        self
+    }
+
+    #[inline]
+    /// Limit downloading speed to specified amount of bytes per second
+    pub fn with_speed_limit(mut self, speed_limit: Option<u64>) -> Self {
+        self.speed_limit = speed_limit;
+
+        self
+    }
+
+    #[inline]
+    /// Specify proxy used to download files
+    pub fn with_proxy(mut self, proxy: impl ToString) -> Self {
+        self.proxy = Some(proxy.to_string());
+
+        self
+    }
+
+    #[inline]
+    /// Specify value of the `User-Agent` header used to download files
+    pub fn with_user_agent(mut self, user_agent: impl ToString) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+
+        self
+    }
+
+    #[inline]
+    /// Add header sent with downloading requests
+    pub fn with_header(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.headers.push((key.to_string(), value.to_string()));
+
+        self
+    }
+
+    #[inline]
+    /// Specify credentials sent with downloading requests
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+
+        self
+    }
+
+    #[inline]
+    /// Specify amount of connections used to download every file
+    pub fn with_connections(mut self, connections: u8) -> Self {
+        self.connections = connections;
+
+        self
+    }
+
+    #[inline]
+    /// Specify amount of bytes buffered in memory before they're written to the downloaded file
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether the archive should be extracted while it's being downloaded
+    pub fn with_stream_extract(mut self, stream_extract: bool) -> Self {
+        self.stream_extract = stream_extract;
+
+        self
+    }
+
+    #[inline]
+    /// Specify temp folder used to store downloaded archives
+    pub fn with_temp_folder(mut self, temp_folder: impl Into<PathBuf>) -> Self {
+        self.temp_folder = Some(temp_folder.into());
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether another folder should be used if the temp folder can't be used
+    pub fn with_temp_fallback(mut self, temp_fallback: bool) -> Self {
+        self.temp_fallback = temp_fallback;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether the installation folder should be used to store downloaded archives
+    /// if the temp folder is stored on another filesystem
+    pub fn with_relocate_temp(mut self, relocate_temp: bool) -> Self {
+        self.relocate_temp = relocate_temp;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether downloaded archives should be kept in the temp folder if they couldn't be verified or unpacked
+    pub fn with_keep_temp_on_error(mut self, keep_temp_on_error: bool) -> Self {
+        self.keep_temp_on_error = keep_temp_on_error;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether unpacked files should keep modification times stored in the archive
+    pub fn with_preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether archives unpacking should use less memory at the cost of its speed
+    pub fn with_low_memory(mut self, low_memory: bool) -> Self {
+        self.low_memory = low_memory;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether archive entries identical to the existing files should be skipped
+    pub fn with_skip_identical(mut self, skip_identical: bool) -> Self {
+        self.skip_identical = skip_identical;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether outdated files listed in the `deletefiles.txt` should be removed
+    pub fn with_apply_deletefiles(mut self, apply_deletefiles: bool) -> Self {
+        self.apply_deletefiles = apply_deletefiles;
+
+        self
+    }
+
+    #[inline]
+    /// Specify whether changed files should be restored if installation fails
+    pub fn with_transactional(mut self, transactional: bool) -> Self {
+        self.transactional = transactional;
+
+        self
+    }
+
+    #[inline]
+    /// Install only files which paths start with one of these prefixes
+    pub fn with_extract_prefixes<T: ToString>(mut self, prefixes: impl IntoIterator<Item = T>) -> Self {
+        self.extract_prefixes = Some(prefixes.into_iter().map(|prefix| prefix.to_string()).collect());
+
+        self
+    }
+
+    /// Check if the file should be installed according to the `extract_prefixes`
+    ///
+    /// `path` is relative to the installation folder
+    pub fn is_included(&self, path: impl AsRef<Path>) -> bool {
+        let Some(prefixes) = &self.extract_prefixes else {
+            return true;
+        };
+
+        let path = path.as_ref().to_string_lossy().replace('\\', "/");
+        let path = path.trim_start_matches("./");
+
+        if path == manifest::HDIFF_FILES || path == manifest::DELETE_FILES {
+            return true;
+        }
+
+        prefixes.iter().any(|prefix| path.starts_with(prefix.trim_start_matches("./")))
+    }
+
+    #[inline]
+    /// Specify tool used to apply hdiff patches
+    pub fn with_patcher(mut self, patcher: impl HdiffPatcher + 'static) -> Self {
+        self.patcher = Arc::new(patcher);
+
+        self
+    }
+
+    #[inline]
+    /// Check if the installation process was asked to be stopped
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_flag.as_ref()
+            .map(|flag| flag.load(Ordering::Relaxed))
+            .unwrap_or(false)
+    }
+
+    /// Create downloader for the uri using downloading-related options
+    pub(crate) fn downloader(&self, uri: impl AsRef<str>) -> Result<Downloader, DownloadingError> {
+        let mut downloader = Downloader::new_with_proxy(uri, self.proxy.clone())?;
+
+        // Content length is requested again because server may not respond without these headers
+        if self.user_agent.is_some() || !self.headers.is_empty() || self.auth.is_some() {
+            if let Some(user_agent) = &self.user_agent {
+                downloader.set_user_agent(user_agent);
+            }
+
+            if let Some(auth) = &self.auth {
+                downloader.set_auth(auth.clone());
+            }
+
+            for (key, value) in &self.headers {
+                downloader.add_header(key, value);
+            }
+
+            downloader.update_length()?;
+        }
+
+        if let Some(cancel_flag) = &self.cancel_flag {
+            downloader = downloader.with_cancel_flag(cancel_flag.clone());
+        }
+
+        if let Some(pause_flag) = &self.pause_flag {
+            downloader = downloader.with_pause_flag(pause_flag.clone());
+        }
+
+        Ok(downloader.with_speed_limit(self.speed_limit)
+            .with_connections(self.connections)
+            .with_chunk_size(self.chunk_size))
+    }
+}
@@ -0,0 +1,106 @@
+use std::path::Path;
+
+use crate::external::hpatchz;
+
+pub use crate::external::hpatchz::HdiffPatchError;
+
+/// What should be done with the file before applying the hdiff patch to it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PatchDecision {
+    /// Apply the patch
+    #[default]
+    Apply,
+
+    /// Keep the file unchanged and remove its patch,
+    /// e.g. if the file is locked by another program
+    Skip
+}
+
+/// Tool used by the version differences to apply hdiff patches
+pub trait HdiffPatcher: std::fmt::Debug + Send + Sync {
+    /// Apply the patch to the source file and save the result as the output file
+    fn patch(&self, source: &Path, patch: &Path, output: &Path) -> Result<(), HdiffPatchError>;
+
+    /// Decide whether the patch should be applied to the file
+    ///
+    /// Called from the hdiff workers before patching every file. Skipped files are listed
+    /// in the `InstallReport::skipped_patches` and can be fixed later by the files repairer.
+    /// Default implementation applies all the patches
+    fn decide(&self, file: &Path) -> PatchDecision {
+        let _ = file;
+
+        PatchDecision::Apply
+    }
+
+    /// Check if the patcher can be used
+    ///
+    /// Checked before applying any patch. Default implementation always returns `true`
+    fn is_available(&self) -> bool {
+        true
+    }
+}
+
+/// Default patcher which runs the `hpatchz` binary
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Hpatchz;
+
+impl HdiffPatcher for Hpatchz {
+    #[inline]
+    fn patch(&self, source: &Path, patch: &Path, output: &Path) -> Result<(), HdiffPatchError> {
+        hpatchz::patch(source, patch, output)
+    }
+
+    #[inline]
+    fn is_available(&self) -> bool {
+        hpatchz::is_available()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Patcher which replaces files by their patches
+    #[derive(Debug)]
+    pub(crate) struct CopyPatcher;
+
+    impl HdiffPatcher for CopyPatcher {
+        fn patch(&self, _source: &Path, patch: &Path, output: &Path) -> Result<(), HdiffPatchError> {
+            std::fs::copy(patch, output).map(|_| ()).map_err(|err| HdiffPatchError {
+                file: patch.to_path_buf(),
+                exit_code: None,
+                stderr: err.to_string()
+            })
+        }
+    }
+
+    /// Patcher which skips files named `skipped.bin`
+    #[derive(Debug)]
+    pub(crate) struct SkippingPatcher;
+
+    impl HdiffPatcher for SkippingPatcher {
+        fn patch(&self, source: &Path, patch: &Path, output: &Path) -> Result<(), HdiffPatchError> {
+            CopyPatcher.patch(source, patch, output)
+        }
+
+        fn decide(&self, file: &Path) -> PatchDecision {
+            if file.ends_with("skipped.bin") {
+                PatchDecision::Skip
+            } else {
+                PatchDecision::Apply
+            }
+        }
+    }
+
+    /// Patcher which writes the patched file and stops as if the process was killed
+    #[derive(Debug)]
+    pub(crate) struct InterruptedPatcher;
+
+    impl HdiffPatcher for InterruptedPatcher {
+        fn patch(&self, _source: &Path, patch: &Path, output: &Path) -> Result<(), HdiffPatchError> {
+            std::fs::copy(patch, output).unwrap();
+
+            panic!("Installation process is interrupted");
+        }
+    }
+}
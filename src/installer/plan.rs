@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+
+use serde::{Serialize, Deserialize};
+
+/// Description of the changes the version difference installation is going to make
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallPlan {
+    /// Uris of the archives which will be downloaded
+    pub uris: Vec<String>,
+
+    /// Total size of the downloaded archives
+    pub downloaded_size: u64,
+
+    /// Total size of the unpacked data
+    pub unpacked_size: u64,
+
+    /// Files which will be updated by the hdiff patches
+    ///
+    /// Known only if the `hdifffiles.txt` is already present in the installation folder
+    pub patched_files: Vec<PathBuf>,
+
+    /// Outdated files which will be removed
+    ///
+    /// Known only if the `deletefiles.txt` is already present in the installation folder
+    pub deleted_files: Vec<PathBuf>,
+
+    /// Total size of the outdated files which currently exist on disk
+    pub freed_size: u64
+}
+
+impl InstallPlan {
+    #[inline]
+    /// Estimate how installation will change used disk space
+    ///
+    /// Negative value means that installation will free some space
+    pub fn net_size(&self) -> i64 {
+        self.unpacked_size as i64 - self.freed_size as i64
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+
+use super::downloader::DownloadInfo;
+
+/// Summary of changes made by the `install_to_by`-like methods of the version differences
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallReport {
+    /// Files updated by the hdiff patches listed in the `hdifffiles.txt`
+    pub patched_files: Vec<PathBuf>,
+
+    /// Files which failed to be patched if `InstallOptions::continue_on_patch_error` is set
+    ///
+    /// `(path, error message)`
+    pub failed_patches: Vec<(PathBuf, String)>,
+
+    /// Files which weren't patched because the patcher decided to skip them
+    ///
+    /// See `HdiffPatcher::decide`
+    pub skipped_patches: Vec<PathBuf>,
+
+    /// Outdated files listed in the `deletefiles.txt` which were removed
+    pub deleted_files: Vec<PathBuf>,
+
+    /// Missing or corrupted files which were re-downloaded
+    pub repaired_files: Vec<PathBuf>,
+
+    /// Total size of the extracted archive entries
+    pub unpacked_bytes: u64,
+
+    /// Servers the archives were downloaded from
+    pub downloads: Vec<DownloadInfo>,
+
+    /// Free space of the installation folder's volume
+    ///
+    /// `None` if it couldn't be measured
+    pub install_free_space: Option<FreeSpaceUsage>,
+
+    /// Free space of the temp folder's volume
+    ///
+    /// `None` if it couldn't be measured
+    pub temp_free_space: Option<FreeSpaceUsage>
+}
+
+/// Free space of the volume measured before and after the installation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FreeSpaceUsage {
+    /// Folder stored on the measured volume
+    pub path: PathBuf,
+
+    /// Available bytes before downloading the archives
+    pub before: u64,
+
+    /// Available bytes after the installation and temp files removal
+    pub after: u64
+}
+
+impl FreeSpaceUsage {
+    #[inline]
+    /// Measure free space of the volume storing the folder
+    ///
+    /// Return `None` if the folder is not mounted
+    pub fn measure(path: impl AsRef<Path>) -> Option<Self> {
+        let path = path.as_ref();
+
+        super::free_space::available(path).map(|space| Self {
+            path: path.to_path_buf(),
+            before: space,
+            after: space
+        })
+    }
+
+    #[inline]
+    /// Update available space after the installation
+    pub fn update(&mut self) {
+        if let Some(space) = super::free_space::available(&self.path) {
+            self.after = space;
+        }
+    }
+
+    #[inline]
+    /// Get amount of bytes consumed by the installation
+    ///
+    /// Return 0 if the volume has more free space than before
+    pub fn consumed(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+use std::collections::HashSet;
+
+/// Journal of the files changed by the installation used to restore them if it fails
+///
+/// Files are copied to the backup folder before they're modified or removed,
+/// and files which didn't exist are removed on rollback. Created folders are kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    backup_folder: PathBuf,
+
+    /// `(path, backup path)` pairs. Backup path is `None` for created files
+    files: Vec<(PathBuf, Option<PathBuf>)>,
+
+    journaled: HashSet<PathBuf>
+}
+
+impl Transaction {
+    #[inline]
+    pub fn new(backup_folder: impl Into<PathBuf>) -> Self {
+        Self {
+            backup_folder: backup_folder.into(),
+            files: Vec::new(),
+            journaled: HashSet::new()
+        }
+    }
+
+    #[inline]
+    /// Get folder used to store backups of the changed files
+    pub fn backup_folder(&self) -> &Path {
+        &self.backup_folder
+    }
+
+    /// Save the file before it's modified, removed or created
+    ///
+    /// Only the first call for every path is journaled. Folders are ignored
+    pub fn backup(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+
+        if self.journaled.contains(path) {
+            return Ok(());
+        }
+
+        let backup = if path.is_file() {
+            let backup = self.backup_folder.join(self.files.len().to_string());
+
+            std::fs::create_dir_all(&self.backup_folder)?;
+            std::fs::copy(path, &backup)?;
+
+            Some(backup)
+        }
+
+        else if path.exists() {
+            return Ok(());
+        }
+
+        else {
+            None
+        };
+
+        self.journaled.insert(path.to_path_buf());
+        self.files.push((path.to_path_buf(), backup));
+
+        Ok(())
+    }
+
+    /// Remove backups of the changed files
+    pub fn commit(self) -> std::io::Result<()> {
+        if self.backup_folder.exists() {
+            std::fs::remove_dir_all(&self.backup_folder)?;
+        }
+
+        Ok(())
+    }
+
+    /// Restore all the journaled files and remove the backups
+    ///
+    /// Rollback doesn't stop on errors to restore as much files as possible.
+    /// The last error is returned and backups are kept in this case
+    pub fn rollback(self) -> std::io::Result<()> {
+        let mut result = Ok(());
+
+        for (path, backup) in self.files.into_iter().rev() {
+            let restored = match backup {
+                Some(backup) => path.parent()
+                    .map(std::fs::create_dir_all)
+                    .unwrap_or(Ok(()))
+                    .and_then(|_| std::fs::copy(backup, &path))
+                    .map(|_| ()),
+
+                None if path.symlink_metadata().is_ok() => std::fs::remove_file(&path),
+                None => Ok(())
+            };
+
+            if let Err(err) = restored {
+                tracing::error!("Failed to restore file {:?}: {err}", path);
+
+                result = Err(err);
+            }
+        }
+
+        if result.is_ok() && self.backup_folder.exists() {
+            std::fs::remove_dir_all(&self.backup_folder)?;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::installer::downloader::tests::temp_file;
+
+    #[test]
+    fn test_rollback() {
+        let folder = temp_file("transaction");
+
+        std::fs::create_dir_all(&folder).unwrap();
+        std::fs::write(folder.join("modified"), "original").unwrap();
+        std::fs::write(folder.join("removed"), "original").unwrap();
+
+        let mut transaction = Transaction::new(folder.join("backup"));
+
+        transaction.backup(folder.join("modified")).unwrap();
+        transaction.backup(folder.join("removed")).unwrap();
+        transaction.backup(folder.join("created")).unwrap();
+
+        std::fs::write(folder.join("modified"), "modified").unwrap();
+        std::fs::remove_file(folder.join("removed")).unwrap();
+        std::fs::write(folder.join("created"), "created").unwrap();
+
+        // Only the first state is journaled
+        transaction.backup(folder.join("modified")).unwrap();
+
+        transaction.rollback().unwrap();
+
+        assert_eq!(std::fs::read_to_string(folder.join("modified")).unwrap(), "original");
+        assert_eq!(std::fs::read_to_string(folder.join("removed")).unwrap(), "original");
+
+        assert!(!folder.join("created").exists());
+        assert!(!folder.join("backup").exists());
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+}
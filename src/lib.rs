@@ -15,6 +15,7 @@ pub mod version;
 pub mod traits;
 pub mod prettify_bytes;
 pub mod check_domain;
+pub mod endpoints;
 
 #[cfg(feature = "patches")]
 pub mod patches;
@@ -1,11 +1,126 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::io::Read;
 use std::collections::HashSet;
 
 use md5::{Md5, Digest};
 
 use super::installer::downloader::{Downloader, DownloadingError};
 
-// {"remoteName": "UnityPlayer.dll", "md5": "8c8c3d845b957e4cb84c662bed44d072", "fileSize": 33466104}
+/// Size of the chunks files are read by when their hashes are calculated
+const HASHING_CHUNK_SIZE: usize = 1024 * 1024; // 1 MB
+
+/// Files created by the launcher in the game folder which aren't a part of the game
+pub const LAUNCHER_FILES: &[&str] = &[".version", ".anime-game-core-probe"];
+
+#[inline]
+/// Calculate MD5 hash of the file without reading it into memory entirely
+///
+/// Hash is returned as a lowercase hex string, the same way it's stored in the game manifests
+pub fn file_md5(path: impl AsRef<Path>) -> std::io::Result<String> {
+    file_md5_with_progress(path, |_, _| {})
+}
+
+/// Calculate MD5 hash of the file without reading it into memory entirely
+///
+/// `progress` is called with `(hashed bytes, file size)` values after every chunk of the file
+pub fn file_md5_with_progress(path: impl AsRef<Path>, progress: impl Fn(u64, u64)) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let total = file.metadata()?.len();
+
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; HASHING_CHUNK_SIZE];
+    let mut hashed = 0;
+
+    progress(0, total);
+
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err)
+        };
+
+        hasher.update(&buffer[..read]);
+
+        hashed += read as u64;
+
+        progress(hashed, total);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Entry of the `pkg_version` manifest
+///
+/// `{"remoteName": "UnityPlayer.dll", "md5": "8c8c3d845b957e4cb84c662bed44d072", "fileSize": 33466104}`
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PkgVersionEntry {
+    /// Path to the file relative to the game folder
+    #[serde(rename = "remoteName")]
+    pub remote_name: String,
+
+    pub md5: String,
+
+    #[serde(rename = "fileSize")]
+    pub size: u64
+}
+
+impl PkgVersionEntry {
+    #[inline]
+    /// Convert the entry to the integrity information of the file which can be downloaded from `base_url`
+    pub fn to_integrity_file(&self, base_url: impl ToString) -> IntegrityFile {
+        IntegrityFile {
+            path: PathBuf::from(&self.remote_name),
+            md5: self.md5.clone(),
+            size: self.size,
+            base_url: base_url.to_string()
+        }
+    }
+}
+
+/// Iterator over entries of the `pkg_version`-like manifest
+///
+/// Every line of the manifest is a JSON object describing one file. Lines which can't be parsed are skipped
+///
+/// ```
+/// use anime_game_core::repairer::PkgVersion;
+///
+/// let manifest = concat!(
+///     r#"{"remoteName": "UnityPlayer.dll", "md5": "8c8c3d845b957e4cb84c662bed44d072", "fileSize": 33466104}"#, "\n",
+///     r#"{"remoteName": "GameAssembly.dll", "md5": "c4be4ba53e0b0a56d2f2b6e2d0d1d3d9", "fileSize": 128}"#
+/// );
+///
+/// let sizes = PkgVersion::new(manifest)
+///     .map(|entry| entry.size)
+///     .sum::<u64>();
+///
+/// assert_eq!(sizes, 33466232);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PkgVersion<'a> {
+    lines: std::str::Lines<'a>
+}
+
+impl<'a> PkgVersion<'a> {
+    #[inline]
+    pub fn new(manifest: &'a str) -> Self {
+        Self {
+            lines: manifest.lines()
+        }
+    }
+}
+
+impl Iterator for PkgVersion<'_> {
+    type Item = PkgVersionEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines.by_ref()
+            .find_map(|line| serde_json::from_str(line).ok())
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct IntegrityFile {
     pub path: PathBuf,
@@ -36,8 +151,8 @@ impl IntegrityFile {
         else {
             tracing::trace!("Comparing hashes");
 
-            match std::fs::read(&file_path) {
-                Ok(hash) => format!("{:x}", Md5::digest(hash)).to_ascii_lowercase() == self.md5.to_ascii_lowercase(),
+            match file_md5(&file_path) {
+                Ok(hash) => hash.to_ascii_lowercase() == self.md5.to_ascii_lowercase(),
                 Err(_) => false
             }
         }
@@ -53,6 +168,13 @@ impl IntegrityFile {
             .unwrap_or(false)
     }
 
+    /// Get url this file can be downloaded from
+    pub fn url(&self) -> String {
+        let path = self.path.to_string_lossy().replace('\\', "/");
+
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path.trim_start_matches('/'))
+    }
+
     /// Replace remote file with the latest one
     /// 
     /// This method doesn't compare them, so you should do it manually
@@ -60,7 +182,7 @@ impl IntegrityFile {
     pub fn repair<T: Into<PathBuf> + std::fmt::Debug>(&self, game_path: T) -> Result<(), DownloadingError> {
         tracing::debug!("Repairing file");
 
-        let mut downloader = Downloader::new(format!("{}/{}", self.base_url, self.path.to_string_lossy()))?;
+        let mut downloader = Downloader::new(self.url())?;
 
         // Obviously re-download file entirely
         downloader.continue_downloading = false;
@@ -69,6 +191,303 @@ impl IntegrityFile {
     }
 }
 
+/// File which doesn't match its integrity information
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum CorruptFile {
+    /// File doesn't exist
+    Missing(IntegrityFile),
+
+    /// File has wrong size or MD5 hash
+    Mismatched(IntegrityFile)
+}
+
+impl CorruptFile {
+    #[inline]
+    /// Get integrity information of the file, e.g. to repair it
+    pub fn file(&self) -> &IntegrityFile {
+        match self {
+            Self::Missing(file) |
+            Self::Mismatched(file) => file
+        }
+    }
+}
+
+/// Parse `pkg_version`-like manifest
+///
+/// See `PkgVersion` for details. `base_url` is used by `IntegrityFile::repair` to download the file
+pub fn parse_integrity_files(manifest: impl AsRef<str>, base_url: impl AsRef<str>) -> Vec<IntegrityFile> {
+    PkgVersion::new(manifest.as_ref())
+        .map(|entry| entry.to_integrity_file(base_url.as_ref()))
+        .collect()
+}
+
+/// Read `pkg_version` and `Audio_*_pkg_version` manifests stored in the game folder
+///
+/// Returned files will have empty `base_url`, so they can't be repaired
+pub fn read_local_integrity_files(game_dir: impl AsRef<Path>) -> std::io::Result<Vec<IntegrityFile>> {
+    let game_dir = game_dir.as_ref();
+
+    let mut files = parse_integrity_files(std::fs::read_to_string(game_dir.join("pkg_version"))?, "");
+
+    for entry in std::fs::read_dir(game_dir)?.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if name.starts_with("Audio_") && name.ends_with("_pkg_version") {
+            files.append(&mut parse_integrity_files(std::fs::read_to_string(entry.path())?, ""));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Verify listed files stored in the `game_dir`
+///
+/// Return list of missing or corrupted files' paths relative to the `game_dir`
+///
+/// `progress` is called with `(checked files, total files)` values
+pub fn verify_files(game_dir: impl AsRef<Path>, files: &[IntegrityFile], progress: impl Fn(u64, u64)) -> Vec<PathBuf> {
+    let game_dir = game_dir.as_ref();
+    let total = files.len() as u64;
+
+    let mut broken = Vec::new();
+
+    for (i, file) in files.iter().enumerate() {
+        if !file.verify(game_dir) {
+            broken.push(file.path.clone());
+        }
+
+        (progress)(i as u64 + 1, total);
+    }
+
+    broken
+}
+
+/// Download `pkg_version`-like manifest and verify files stored in the `game_dir`
+///
+/// Files are repaired from the manifest's folder, so returned files can be fixed by `IntegrityFile::repair`
+///
+/// `progress` is called with `(checked files, total files)` values
+pub fn verify_installation(game_dir: impl AsRef<Path>, manifest_url: impl AsRef<str>, progress: impl Fn(u64, u64)) -> anyhow::Result<Vec<CorruptFile>> {
+    let game_dir = game_dir.as_ref();
+    let manifest_url = manifest_url.as_ref();
+
+    let response = minreq::get(manifest_url)
+        .with_timeout(*crate::REQUESTS_TIMEOUT)
+        .send()?;
+
+    if response.status_code >= 400 {
+        anyhow::bail!("Failed to download manifest {manifest_url}: status code {}", response.status_code);
+    }
+
+    let base_url = manifest_url.rsplit_once('/')
+        .map(|(base_url, _)| base_url)
+        .unwrap_or(manifest_url);
+
+    let files = parse_integrity_files(response.as_str()?, base_url);
+    let total = files.len() as u64;
+
+    let mut corrupted = Vec::new();
+
+    for (i, file) in files.into_iter().enumerate() {
+        let path = game_dir.join(&file.path);
+
+        if !path.exists() {
+            corrupted.push(CorruptFile::Missing(file));
+        }
+
+        else if !file.verify(game_dir) {
+            corrupted.push(CorruptFile::Mismatched(file));
+        }
+
+        (progress)(i as u64 + 1, total);
+    }
+
+    Ok(corrupted)
+}
+
+/// Verify listed files stored in the `game_dir` and re-download missing or corrupted ones
+///
+/// Files must have `base_url` they can be downloaded from, so
+/// they can't be read by the `read_local_integrity_files` function
+///
+/// Return list of repaired files' paths relative to the `game_dir`
+///
+/// `progress` is called with `(processed files, total files)` values
+pub fn repair_files(game_dir: impl AsRef<Path>, files: &[IntegrityFile], progress: impl Fn(u64, u64)) -> Result<Vec<PathBuf>, DownloadingError> {
+    let game_dir = game_dir.as_ref();
+    let total = files.len() as u64;
+
+    let mut repaired = Vec::new();
+
+    for (i, file) in files.iter().enumerate() {
+        if !file.verify(game_dir) {
+            tracing::debug!("Repairing corrupted file: {:?}", file.path);
+
+            file.repair(game_dir)?;
+
+            repaired.push(file.path.clone());
+        }
+
+        (progress)(i as u64 + 1, total);
+    }
+
+    Ok(repaired)
+}
+
+#[inline]
+/// List all the files stored in the `game_dir` with their sizes
+///
+/// See `scan_install_excluding` for details
+pub fn scan_install(game_dir: impl AsRef<Path>) -> std::io::Result<Vec<(PathBuf, u64)>> {
+    scan_install_excluding(game_dir, &[] as &[&str])
+}
+
+/// List all the files stored in the `game_dir` with their sizes, except the excluded ones
+///
+/// Returned paths are relative to the `game_dir` and sorted. Symlinks are followed
+/// and listed with their targets' sizes, broken symlinks and already visited folders
+/// are skipped. `LAUNCHER_FILES` stored in the root of the `game_dir` are skipped as well
+///
+/// `exclude` patterns are matched against relative paths with `/` separators, or against
+/// file names if the pattern doesn't contain `/`. `*` matches any sequence of characters
+/// and `?` matches any single character. Excluded folders are not scanned
+pub fn scan_install_excluding(game_dir: impl AsRef<Path>, exclude: &[impl AsRef<str>]) -> std::io::Result<Vec<(PathBuf, u64)>> {
+    let game_dir = game_dir.as_ref();
+
+    let exclude = exclude.iter()
+        .map(|pattern| pattern.as_ref().trim_start_matches("./").trim_end_matches('/'))
+        .collect::<Vec<_>>();
+
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut folders = vec![PathBuf::new()];
+
+    visited.insert(game_dir.canonicalize()?);
+
+    while let Some(folder) = folders.pop() {
+        let entries = match std::fs::read_dir(game_dir.join(&folder)) {
+            Ok(entries) => entries,
+
+            // The scanned folder itself must be readable
+            Err(err) if folder.as_os_str().is_empty() => return Err(err),
+
+            Err(err) => {
+                tracing::warn!("Failed to read folder {:?}: {err}", folder);
+
+                continue;
+            }
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let path = folder.join(entry.file_name());
+
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            if folder.as_os_str().is_empty() && LAUNCHER_FILES.contains(&name.as_ref()) {
+                continue;
+            }
+
+            let relative = path.to_string_lossy().replace('\\', "/");
+
+            let excluded = exclude.iter().any(|pattern| {
+                if pattern.contains('/') {
+                    wildcard_match(pattern, &relative)
+                } else {
+                    wildcard_match(pattern, &name)
+                }
+            });
+
+            if excluded {
+                continue;
+            }
+
+            let mut file_type = entry.file_type()?;
+
+            // Resolve symlinks only, so regular entries need no additional syscalls
+            let metadata = if file_type.is_symlink() {
+                match std::fs::metadata(entry.path()) {
+                    Ok(metadata) => {
+                        file_type = metadata.file_type();
+
+                        Some(metadata)
+                    }
+
+                    Err(err) => {
+                        tracing::debug!("Skipping broken symlink {:?}: {err}", path);
+
+                        continue;
+                    }
+                }
+            } else {
+                None
+            };
+
+            if file_type.is_dir() {
+                // Prevent symlink loops
+                if !visited.insert(entry.path().canonicalize()?) {
+                    continue;
+                }
+
+                folders.push(path);
+            }
+
+            else {
+                let size = match metadata {
+                    Some(metadata) => metadata.len(),
+                    None => entry.metadata()?.len()
+                };
+
+                files.push((path, size));
+            }
+        }
+    }
+
+    files.sort_unstable();
+
+    Ok(files)
+}
+
+/// Match `text` against `pattern` with `*` and `?` wildcards
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    let mut i = 0;
+    let mut j = 0;
+
+    // Position of the last `*` in the pattern and the text position it matched until
+    let mut star = None;
+
+    while j < text.len() {
+        if i < pattern.len() && (pattern[i] == '?' || pattern[i] == text[j]) {
+            i += 1;
+            j += 1;
+        }
+
+        else if i < pattern.len() && pattern[i] == '*' {
+            star = Some((i, j));
+
+            i += 1;
+        }
+
+        else if let Some((star_i, star_j)) = star {
+            star = Some((star_i, star_j + 1));
+
+            i = star_i + 1;
+            j = star_j + 1;
+        }
+
+        else {
+            return false;
+        }
+    }
+
+    pattern[i..].iter().all(|c| *c == '*')
+}
+
 /// Calculate difference between actual files stored in `game_dir`, and files listed in `used_files`
 /// 
 /// Returned difference will contain files that are not used by the game and should (or just can) be deleted
@@ -140,3 +559,124 @@ where
         })
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::installer::downloader::tests::{serve, temp_file};
+
+    #[test]
+    fn test_verify_installation() {
+        let uri = serve("200 OK", &[], concat!(
+            r#"{"remoteName": "file.zip", "md5": "827ccb0eea8a706c4c34a16891f84e7b", "fileSize": 5}"#, "\n",
+            r#"{"remoteName": "missing.zip", "md5": "827ccb0eea8a706c4c34a16891f84e7b", "fileSize": 5}"#, "\n",
+            r#"{"remoteName": "broken.zip", "md5": "827ccb0eea8a706c4c34a16891f84e7b", "fileSize": 5}"#
+        ).as_bytes());
+
+        let game_dir = temp_file("verify-installation");
+
+        std::fs::create_dir_all(&game_dir).unwrap();
+        std::fs::write(game_dir.join("file.zip"), b"12345").unwrap();
+        std::fs::write(game_dir.join("broken.zip"), b"54321").unwrap();
+
+        let corrupted = verify_installation(&game_dir, uri.replace("file.zip", "pkg_version"), |_, _| {}).unwrap();
+
+        assert_eq!(corrupted.len(), 2);
+
+        assert!(matches!(&corrupted[0], CorruptFile::Missing(file) if file.path == Path::new("missing.zip")));
+        assert!(matches!(&corrupted[1], CorruptFile::Mismatched(file) if file.path == Path::new("broken.zip")));
+
+        assert_eq!(corrupted[0].file().base_url, uri.trim_end_matches("/file.zip"));
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+
+    #[test]
+    fn test_repair_files() {
+        let uri = serve("200 OK", &[("content-length", "5")], b"12345");
+        let game_dir = temp_file("repair-files");
+
+        std::fs::create_dir_all(&game_dir).unwrap();
+        std::fs::write(game_dir.join("file.zip"), b"54321").unwrap();
+
+        let files = [IntegrityFile {
+            path: PathBuf::from("file.zip"),
+            md5: String::from("827ccb0eea8a706c4c34a16891f84e7b"),
+            size: 5,
+            base_url: uri.trim_end_matches("/file.zip").to_string()
+        }];
+
+        assert_eq!(verify_files(&game_dir, &files, |_, _| {}), vec![PathBuf::from("file.zip")]);
+        assert_eq!(repair_files(&game_dir, &files, |_, _| {}).unwrap(), vec![PathBuf::from("file.zip")]);
+
+        assert_eq!(std::fs::read(game_dir.join("file.zip")).unwrap(), b"12345");
+        assert!(repair_files(&game_dir, &files, |_, _| {}).unwrap().is_empty());
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+
+    #[test]
+    fn test_scan_install() {
+        let game_dir = temp_file("scan-install");
+
+        std::fs::create_dir_all(game_dir.join("Game_Data/Persistent")).unwrap();
+        std::fs::write(game_dir.join("Game_Data/level0"), b"12345").unwrap();
+        std::fs::write(game_dir.join("Game_Data/Persistent/cache"), b"123").unwrap();
+        std::fs::write(game_dir.join("config.ini"), b"[general]").unwrap();
+        std::fs::write(game_dir.join("game.exe"), b"1").unwrap();
+        std::fs::write(game_dir.join(".version"), b"123").unwrap();
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(game_dir.join("game.exe"), game_dir.join("link.exe")).unwrap();
+            std::os::unix::fs::symlink(game_dir.join("missing"), game_dir.join("broken")).unwrap();
+            std::os::unix::fs::symlink(&game_dir, game_dir.join("Game_Data/loop")).unwrap();
+        }
+
+        let files = scan_install_excluding(&game_dir, &["*.ini", "Game_Data/Persist*"]).unwrap();
+
+        let mut expected = vec![
+            (PathBuf::from("Game_Data/level0"), 5),
+            (PathBuf::from("game.exe"), 1)
+        ];
+
+        #[cfg(unix)]
+        expected.push((PathBuf::from("link.exe"), 1));
+
+        assert_eq!(files, expected);
+        assert_eq!(scan_install(&game_dir).unwrap().len(), expected.len() + 2);
+
+        std::fs::remove_dir_all(game_dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_url() {
+        let file = IntegrityFile {
+            path: PathBuf::from("AnimeGame_Data/level0"),
+            md5: String::new(),
+            size: 0,
+            base_url: String::from("https://example.com/files/")
+        };
+
+        assert_eq!(file.url(), "https://example.com/files/AnimeGame_Data/level0");
+    }
+
+    #[test]
+    fn test_file_md5_with_progress() {
+        let path = temp_file("file-md5");
+
+        std::fs::write(&path, b"12345").unwrap();
+
+        let progress = std::cell::RefCell::new(Vec::new());
+
+        let hash = file_md5_with_progress(&path, |current, total| {
+            progress.borrow_mut().push((current, total));
+        }).unwrap();
+
+        assert_eq!(hash, "827ccb0eea8a706c4c34a16891f84e7b");
+        assert_eq!(progress.into_inner(), [(0, 5), (5, 5)]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
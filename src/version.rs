@@ -2,6 +2,24 @@ use serde::{Serialize, Deserialize};
 
 use std::fmt::{Debug, Display, Formatter};
 use std::cmp::Ordering;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VersionParseError {
+    /// Version string doesn't consist of 3 dot-separated parts
+    ///
+    /// `(version string, parts amount)`
+    #[error("Version {0:?} must consist of 3 parts, got {1}")]
+    WrongPartsAmount(String, usize),
+
+    /// Part of the version string is not a number in `0..=255` range
+    ///
+    /// `(version string, part)`
+    #[error("Version {0:?} has incorrect part {1:?}")]
+    IncorrectPart(String, String)
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Version {
@@ -19,23 +37,16 @@ impl Version {
     #[allow(clippy::should_implement_trait)]
     /// Get `Version` from the string
     /// 
+    /// Use `str::parse` to get the reason why the string is incorrect
+    /// 
     /// ```
     /// use anime_game_core::prelude::Version;
     /// 
     /// let version = Version::from_str("1.10.2").expect("Failed to parse version string");
     /// ```
+    #[inline]
     pub fn from_str<T: AsRef<str>>(str: T) -> Option<Self> {
-        let parts = str.as_ref().split('.').collect::<Vec<&str>>();
-
-        if parts.len() != 3 {
-            return None;
-        }
-
-        if let (Ok(a), Ok(b), Ok(c)) = (parts[0].parse(), parts[1].parse(), parts[2].parse()) {
-            return Some(Version::new(a, b, c));
-        }
-
-        None
+        str.as_ref().parse().ok()
     }
 
     /// Converts `Version` struct to plain format (e.g. "123")
@@ -48,6 +59,30 @@ impl Version {
     pub fn to_plain_string(&self) -> String {
         format!("{}{}{}", self.version[0], self.version[1], self.version[2])
     }
+
+    #[inline]
+    /// Check if this version is newer than the other one
+    /// 
+    /// Versions are compared component-wise, so `3.10.0` is newer than `3.9.0`
+    /// 
+    /// ```
+    /// use anime_game_core::prelude::Version;
+    /// 
+    /// assert!(Version::new(3, 10, 0).is_newer_than(&Version::new(3, 9, 0)));
+    /// ```
+    pub fn is_newer_than(&self, other: &Version) -> bool {
+        self > other
+    }
+
+    /// Compare version with the string
+    /// 
+    /// Strings which can't be parsed as version are compared lexically
+    fn cmp_str(&self, other: &str) -> Option<Ordering> {
+        match other.parse::<Version>() {
+            Ok(other) => Some(self.cmp(&other)),
+            Err(_) => self.to_string().as_str().partial_cmp(other)
+        }
+    }
 }
 
 impl Debug for Version {
@@ -62,61 +97,94 @@ impl Display for Version {
     }
 }
 
+impl FromStr for Version {
+    type Err = VersionParseError;
+
+    /// Parse `Version` from the `x.y.z` string
+    /// 
+    /// ```
+    /// use anime_game_core::prelude::*;
+    /// 
+    /// assert_eq!("3.1.0".parse(), Ok(Version::new(3, 1, 0)));
+    /// assert_eq!("3.1".parse::<Version>(), Err(VersionParseError::WrongPartsAmount(String::from("3.1"), 2)));
+    /// ```
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let parts = str.split('.').collect::<Vec<&str>>();
+
+        if parts.len() != 3 {
+            return Err(VersionParseError::WrongPartsAmount(str.to_string(), parts.len()));
+        }
+
+        let mut version = [0; 3];
+
+        for (i, part) in parts.into_iter().enumerate() {
+            version[i] = part.parse()
+                .map_err(|_| VersionParseError::IncorrectPart(str.to_string(), part.to_string()))?;
+        }
+
+        Ok(Self { version })
+    }
+}
+
 // Equality with strings
+//
+// Strings are parsed the same way as for comparison, so `==` agrees with `partial_cmp`
 
 impl PartialEq<String> for Version {
     #[inline]
     fn eq(&self, other: &String) -> bool {
-        &self.to_string() == other
+        self.cmp_str(other) == Some(Ordering::Equal)
     }
 }
 
 impl PartialEq<Version> for String {
     #[inline]
     fn eq(&self, other: &Version) -> bool {
-        self == &other.to_string()
+        other == self
     }
 }
 
 impl PartialEq<&str> for Version {
     #[inline]
     fn eq(&self, other: &&str) -> bool {
-        &self.to_string() == other
+        self.cmp_str(other) == Some(Ordering::Equal)
     }
 }
 
 impl PartialEq<Version> for &str {
     #[inline]
     fn eq(&self, other: &Version) -> bool {
-        self == &other.to_string()
+        other == self
     }
 }
 
 // Comparison with strings
 
 impl PartialOrd<String> for Version {
+    #[inline]
     fn partial_cmp(&self, other: &String) -> Option<Ordering> {
-        self.to_string().partial_cmp(other)
+        self.cmp_str(other)
     }
 }
 
 impl PartialOrd<Version> for String {
+    #[inline]
     fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
-        self.partial_cmp(&other.to_string())
+        other.cmp_str(self).map(Ordering::reverse)
     }
 }
 
 impl PartialOrd<&str> for Version {
+    #[inline]
     fn partial_cmp(&self, other: &&str) -> Option<Ordering> {
-        self.to_string()
-            .as_str()
-            .partial_cmp(other)
+        self.cmp_str(other)
     }
 }
 
 impl PartialOrd<Version> for &str {
+    #[inline]
     fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
-        self.partial_cmp(&other.to_string().as_str())
+        other.cmp_str(self).map(Ordering::reverse)
     }
 }
 
@@ -169,6 +237,16 @@ mod tests {
         assert_eq!(Version::from_str("0.0."), None);
     }
 
+    #[test]
+    fn test_version_parse() {
+        assert_eq!("3.1.0".parse::<Version>(), Ok(Version::new(3, 1, 0)));
+        assert_eq!("3.1.0".parse::<Version>().unwrap().to_string(), "3.1.0");
+
+        assert_eq!("3.1.0.1".parse::<Version>(), Err(VersionParseError::WrongPartsAmount(String::from("3.1.0.1"), 4)));
+        assert_eq!("3.a.0".parse::<Version>(), Err(VersionParseError::IncorrectPart(String::from("3.a.0"), String::from("a"))));
+        assert_eq!("3.1.256".parse::<Version>(), Err(VersionParseError::IncorrectPart(String::from("3.1.256"), String::from("256"))));
+    }
+
     #[test]
     #[allow(clippy::cmp_owned)]
     fn test_version_comparison() {
@@ -190,4 +268,25 @@ mod tests {
         assert!(Version::new(1, 0, 0) == String::from("1.0.0"));
         assert!(String::from("1.0.0") == Version::new(1, 0, 0));
     }
+
+    #[test]
+    #[allow(clippy::cmp_owned)]
+    fn test_numeric_comparison() {
+        assert!(Version::new(3, 10, 0) > Version::new(3, 9, 0));
+        assert!(Version::new(3, 10, 0).is_newer_than(&Version::new(3, 9, 0)));
+        assert!(!Version::new(3, 9, 0).is_newer_than(&Version::new(3, 10, 0)));
+        assert!(!Version::new(3, 9, 0).is_newer_than(&Version::new(3, 9, 0)));
+
+        assert!(Version::new(3, 10, 0) > "3.9.0");
+        assert!("3.9.0" < Version::new(3, 10, 0));
+        assert!(Version::new(3, 9, 0) < String::from("3.10.0"));
+        assert!(String::from("3.10.0") > Version::new(3, 9, 0));
+
+        // Equality is consistent with the numeric ordering
+        assert!(Version::new(1, 10, 0) == "01.10.0");
+        assert!("01.10.0" == Version::new(1, 10, 0));
+        assert_eq!(Version::new(1, 10, 0).partial_cmp(&"01.10.0"), Some(Ordering::Equal));
+
+        assert!(Version::new(1, 10, 0) != "1.10");
+    }
 }